@@ -0,0 +1,29 @@
+use props::{ArgsSource, EnvSource, LayeredProperties, MemorySource};
+
+fn main() {
+    let mut prop = LayeredProperties::new();
+    let defaults = MemorySource::new("defaults", "server.port=8080\nlog.level=info\n");
+
+    if let Err(e) = prop.add(&defaults) {
+        println!("Load defaults failed, {}", e);
+        return;
+    }
+    prop.mark_final("log.level");
+    if let Err(e) = prop.add(&EnvSource::new("APP")) {
+        println!("Load environment failed, {}", e);
+        return;
+    }
+    if let Err(e) = prop.add(&ArgsSource::new(std::env::args())) {
+        println!("Load arguments failed, {}", e);
+        return;
+    }
+
+    for key in &["server.port", "log.level"] {
+        println!(
+            "{}={} ({})",
+            key,
+            prop.get(key).unwrap(),
+            prop.layer_of(key).unwrap()
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+
+use super::Properties;
+
+#[derive(Default)]
+pub(crate) struct Access {
+    read: HashSet<String>,
+    missing: HashSet<String>,
+}
+
+impl Properties {
+    /// Enables or disables access tracking. While enabled, every key read
+    /// through `get` and its variants (`require`, `require_as`, ...) is
+    /// recorded, enabling it again starts over with an empty record.
+    pub fn track_access(&mut self, val: bool) {
+        self.access = if val { Some(Access::default()) } else { None };
+    }
+
+    // Record a read of key, stored is the key it resolved to (which may
+    // differ in relaxed mode), or None if nothing was found.
+    pub(crate) fn record_access(&mut self, key: &str, stored: Option<&str>) {
+        if let Some(access) = self.access.as_mut() {
+            match stored {
+                Some(k) => access.read.insert(k.to_string()),
+                None => access.missing.insert(key.to_string()),
+            };
+        }
+    }
+
+    /// Returns the keys which exist but have never been read since tracking
+    /// was enabled, in lexical order. Empty if tracking is disabled.
+    pub fn unused_keys(&mut self) -> Vec<String> {
+        let access = match &self.access {
+            Some(access) => access,
+            None => return Vec::new(),
+        };
+        let data = self.data.lock().unwrap();
+        let mut result: Vec<String> = data
+            .keys()
+            .filter(|k| !access.read.contains(*k))
+            .cloned()
+            .collect();
+        result.sort();
+        result
+    }
+
+    /// Returns the keys which have been asked for but are still missing, in
+    /// lexical order. Empty if tracking is disabled.
+    pub fn unknown_reads(&mut self) -> Vec<String> {
+        let access = match &self.access {
+            Some(access) => access,
+            None => return Vec::new(),
+        };
+        let data = self.data.lock().unwrap();
+        let mut result: Vec<String> = access
+            .missing
+            .iter()
+            .filter(|k| !data.contains_key(*k))
+            .cloned()
+            .collect();
+        result.sort();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Properties;
+
+    #[test]
+    fn tracking() {
+        let mut prop = Properties::new();
+        prop.load("db.url=jdbc:h2:mem\ndb.user=root\nserver.port=8080\nlegacy=1\n".as_bytes())
+            .unwrap();
+
+        // nothing is recorded unless enabled
+        prop.get("db.url");
+        assert!(prop.unused_keys().is_empty());
+
+        prop.track_access(true);
+        assert_eq!(
+            prop.unused_keys(),
+            vec!["db.url", "db.user", "legacy", "server.port"]
+        );
+
+        prop.get("db.url");
+        let _ = prop.require_as::<u16>("server.port");
+        let _ = prop.require("db.password");
+        prop.get("client.timeout");
+        assert_eq!(prop.unused_keys(), vec!["db.user", "legacy"]);
+        assert_eq!(prop.unknown_reads(), vec!["client.timeout", "db.password"]);
+
+        // a key set afterwards is no longer unknown
+        prop.set("client.timeout", "30");
+        assert_eq!(prop.unknown_reads(), vec!["db.password"]);
+        assert_eq!(
+            prop.unused_keys(),
+            vec!["client.timeout", "db.user", "legacy"]
+        );
+    }
+
+    #[test]
+    fn relaxed() {
+        let mut prop = Properties::new();
+        prop.set("db.maxSize", "10");
+        prop.relaxed(true);
+        prop.track_access(true);
+        prop.get("DB_MAXSIZE");
+        assert!(prop.unused_keys().is_empty());
+        assert!(prop.unknown_reads().is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::tree::Scope;
+use super::Properties;
+
+type Warning = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// Which value `get` returns when both a key and one of its deprecated
+/// aliases are present.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AliasPolicy {
+    /// The key wins, the deprecated alias is ignored.
+    #[default]
+    PreferKey,
+    /// The deprecated alias wins, e.g. when the key has a default value
+    /// which customers still override with the deprecated one.
+    PreferDeprecated,
+}
+
+#[derive(Default)]
+pub(crate) struct Aliases {
+    // key to its deprecated aliases in order of registration
+    map: HashMap<String, Vec<String>>,
+    policy: AliasPolicy,
+    warning: Option<Warning>,
+    warned: HashSet<String>,
+}
+
+impl Aliases {
+    // The aliases of a copy with scope, see `Properties::subtree`, only the
+    // aliases with both keys copied are kept.
+    pub(crate) fn rebase(&self, scope: &Scope) -> Aliases {
+        let mut map = HashMap::new();
+        for (key, aliases) in &self.map {
+            let key = match scope.copy(key) {
+                Some(key) => key.into_owned(),
+                None => continue,
+            };
+            let aliases: Vec<String> = aliases
+                .iter()
+                .filter_map(|a| scope.copy(a).map(String::from))
+                .collect();
+            if !aliases.is_empty() {
+                map.insert(key, aliases);
+            }
+        }
+        Aliases {
+            map,
+            policy: self.policy,
+            warning: self.warning.clone(),
+            warned: HashSet::new(),
+        }
+    }
+}
+
+impl Properties {
+    /// Registers `deprecated` as an alias of `key`, so that `get(key)` falls
+    /// back to the value of `deprecated` until customers migrate. Several
+    /// aliases of a key are tried in order of registration.
+    pub fn alias(&mut self, deprecated: &str, key: &str) {
+        let aliases = self.aliases.map.entry(key.to_string()).or_default();
+        if !aliases.iter().any(|a| a == deprecated) {
+            aliases.push(deprecated.to_string());
+        }
+    }
+
+    pub fn alias_policy(&mut self, policy: AliasPolicy) {
+        self.aliases.policy = policy;
+    }
+
+    /// Sets the callback called with the deprecated key and its replacement
+    /// the first time `get` finds a deprecated alias set, whether its value
+    /// is used or ignored by the policy.
+    pub fn on_deprecated<F>(&mut self, f: F)
+    where
+        F: Fn(&str, &str) + Send + Sync + 'static,
+    {
+        self.aliases.warning = Some(Arc::new(f));
+    }
+
+    /// Returns the `(deprecated, key)` pairs where both are set, in lexical
+    /// order, e.g. to be reported at startup.
+    pub fn alias_conflicts(&mut self) -> Vec<(String, String)> {
+        let data = self.data.lock().unwrap();
+        let mut result = Vec::new();
+        for (key, aliases) in &self.aliases.map {
+            if self.resolve_key(&data, key).is_none() {
+                continue;
+            }
+            for alias in aliases {
+                if self.resolve_key(&data, alias).is_some() {
+                    result.push((alias.clone(), key.clone()));
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+
+    // Apply the aliases of key on top of stored, the stored key found for
+    // key itself. Returns the stored key to use, and the deprecated alias
+    // found if any.
+    pub(crate) fn resolve_alias(
+        &self,
+        data: &HashMap<String, String>,
+        key: &str,
+        stored: Option<String>,
+    ) -> (Option<String>, Option<String>) {
+        let aliases = match self.aliases.map.get(key) {
+            Some(aliases) => aliases,
+            None => return (stored, None),
+        };
+        let found = aliases
+            .iter()
+            .find_map(|a| self.resolve_key(data, a).map(|k| (a.clone(), k)));
+        match (stored, found) {
+            (None, Some((alias, k))) => (Some(k), Some(alias)),
+            (Some(_), Some((alias, k))) if self.aliases.policy == AliasPolicy::PreferDeprecated => {
+                (Some(k), Some(alias))
+            }
+            (stored, found) => (stored, found.map(|(alias, _)| alias)),
+        }
+    }
+
+    pub(crate) fn warn_deprecated(&mut self, deprecated: &str, key: &str) {
+        if !self.aliases.warned.insert(deprecated.to_string()) {
+            return;
+        }
+        if let Some(f) = &self.aliases.warning {
+            f(deprecated, key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{AliasPolicy, Properties};
+
+    #[test]
+    fn fallback() {
+        let mut prop = Properties::new();
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let cloned = warnings.clone();
+        prop.on_deprecated(move |old, new| {
+            cloned
+                .lock()
+                .unwrap()
+                .push(format!("{} is deprecated, use {}", old, new))
+        });
+        prop.alias("old.name", "new.name");
+        prop.alias("older.name", "new.name");
+
+        assert_eq!(prop.get("new.name"), None);
+        prop.set("older.name", "1");
+        assert_eq!(prop.get("new.name").unwrap(), "1");
+        prop.set("old.name", "2");
+        assert_eq!(prop.get("new.name").unwrap(), "2");
+        assert_eq!(prop.get("new.name").unwrap(), "2");
+        assert_eq!(prop.require("new.name").unwrap(), "2");
+        // the deprecated keys are still readable directly
+        assert_eq!(prop.get("older.name").unwrap(), "1");
+
+        assert_eq!(
+            *warnings.lock().unwrap(),
+            vec![
+                "older.name is deprecated, use new.name",
+                "old.name is deprecated, use new.name"
+            ]
+        );
+    }
+
+    #[test]
+    fn policy() {
+        let mut prop = Properties::new();
+        prop.alias("old.name", "new.name");
+        prop.set("old.name", "old");
+        prop.set("new.name", "new");
+        assert_eq!(prop.get("new.name").unwrap(), "new");
+        assert_eq!(
+            prop.alias_conflicts(),
+            vec![("old.name".to_string(), "new.name".to_string())]
+        );
+
+        prop.alias_policy(AliasPolicy::PreferDeprecated);
+        assert_eq!(prop.get("new.name").unwrap(), "old");
+        prop.remove("old.name");
+        assert_eq!(prop.get("new.name").unwrap(), "new");
+        assert!(prop.alias_conflicts().is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+//! Git merge driver for properties files, merging key by key instead of
+//! line by line. Register it with
+//!
+//! ```text
+//! git config merge.properties.driver "props-merge %O %A %B"
+//! echo "*.properties merge=properties" >> .gitattributes
+//! ```
+//!
+//! The result is written to the file of `%A`, the exit code is 0 for a clean
+//! merge, 1 if there are conflicts and 2 on errors.
+
+use std::fs;
+use std::process::ExitCode;
+
+use props::{merge3, Document};
+
+fn read(path: &str) -> Result<Document, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    Document::parse_bytes(&data).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn run(base: &str, ours: &str, theirs: &str) -> Result<bool, String> {
+    let merged = merge3(&read(base)?, &read(ours)?, &read(theirs)?).map_err(|e| e.to_string())?;
+    fs::write(ours, merged.document().to_string()).map_err(|e| format!("{}: {}", ours, e))?;
+    for conflict in merged.conflicts() {
+        eprintln!("props-merge: conflict on key '{}'", conflict.key());
+    }
+    Ok(merged.conflicts().is_empty())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() != 3 {
+        eprintln!("usage: props-merge <base> <ours> <theirs>");
+        return ExitCode::from(2);
+    }
+    match run(&args[0], &args[1], &args[2]) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("props-merge: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
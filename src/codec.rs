@@ -0,0 +1,284 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use super::query::glob_match;
+use super::tree::Scope;
+use super::{Properties, PropertiesError, Result};
+
+/// Transforms values between their stored and their plain form, e.g. to
+/// decrypt `ENC(...)` values. Registered with `Properties::codec`, the codec
+/// only sees the part between the prefix and the suffix.
+pub trait ValueCodec: Send + Sync {
+    fn decode(&self, encoded: &str) -> std::result::Result<String, Box<dyn Error + Send + Sync>>;
+
+    fn encode(&self, plain: &str) -> std::result::Result<String, Box<dyn Error + Send + Sync>>;
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard Base64 with padding, which is reversible but NOT a protection,
+/// intended for tests and as an example of a `ValueCodec`.
+pub struct Base64Codec;
+
+impl ValueCodec for Base64Codec {
+    fn decode(&self, encoded: &str) -> std::result::Result<String, Box<dyn Error + Send + Sync>> {
+        let data = encoded.as_bytes();
+        if !data.len().is_multiple_of(4) {
+            return Err("invalid base64 length".into());
+        }
+        let mut result = Vec::with_capacity(data.len() / 4 * 3);
+        for (i, chunk) in data.chunks(4).enumerate() {
+            let last = i == data.len() / 4 - 1;
+            let mut bits: u32 = 0;
+            let mut padding = 0;
+            for (j, &c) in chunk.iter().enumerate() {
+                let v = match BASE64.iter().position(|&b| b == c) {
+                    Some(v) if padding == 0 => v as u32,
+                    None if c == b'=' && last && j >= 2 => {
+                        padding += 1;
+                        0
+                    }
+                    _ => return Err(format!("invalid base64 char '{}'", c as char).into()),
+                };
+                bits = (bits << 6) | v;
+            }
+            result.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+        }
+        Ok(String::from_utf8(result)?)
+    }
+
+    fn encode(&self, plain: &str) -> std::result::Result<String, Box<dyn Error + Send + Sync>> {
+        let mut result = String::with_capacity(plain.len().div_ceil(3) * 4);
+        for chunk in plain.as_bytes().chunks(3) {
+            let mut bytes = [0u8; 4];
+            bytes[1..1 + chunk.len()].copy_from_slice(chunk);
+            let bits = u32::from_be_bytes(bytes);
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    result.push(BASE64[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+                } else {
+                    result.push('=');
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Codecs {
+    codecs: Vec<(String, String, Arc<dyn ValueCodec>)>,
+    // key patterns encoded by store, with the prefix of the codec
+    encoded: Vec<(String, String, Scope)>,
+}
+
+impl Codecs {
+    // The codecs of a copy with scope, see `Properties::subtree`.
+    pub(crate) fn rebase(&self, scope: &Scope) -> Codecs {
+        Codecs {
+            codecs: self.codecs.clone(),
+            encoded: self
+                .encoded
+                .iter()
+                .map(|(pattern, prefix, s)| (pattern.clone(), prefix.clone(), scope.compose(s)))
+                .collect(),
+        }
+    }
+}
+
+impl Properties {
+    /// Registers `codec` for the values starting with `prefix` and ending
+    /// with `suffix`, e.g. `ENC(` and `)`. Such values are decoded on every
+    /// `get`, while the stored value is kept as is. The first registered
+    /// codec matching a value is used.
+    pub fn codec<C: ValueCodec + 'static>(&mut self, prefix: &str, suffix: &str, codec: C) {
+        self.codecs
+            .codecs
+            .push((prefix.to_string(), suffix.to_string(), Arc::new(codec)));
+    }
+
+    /// Makes `store` encode the plain values of the keys matching the glob
+    /// `pattern` with the codec registered for `prefix`, values which are
+    /// encoded already are written as is.
+    pub fn encode_pattern(&mut self, pattern: &str, prefix: &str) {
+        self.codecs
+            .encoded
+            .push((pattern.to_string(), prefix.to_string(), Scope::default()));
+    }
+
+    // Decode the stored value of key, returned as is if no codec matches.
+    pub(crate) fn decode_value(&self, key: &str, value: String) -> Result<String> {
+        for (prefix, suffix, codec) in &self.codecs.codecs {
+            if value.len() < prefix.len() + suffix.len()
+                || !value.starts_with(prefix.as_str())
+                || !value.ends_with(suffix.as_str())
+            {
+                continue;
+            }
+            let encoded = &value[prefix.len()..value.len() - suffix.len()];
+            return codec.decode(encoded).map_err(|e| {
+                PropertiesError::with_cause(
+                    format!("decode value of key '{}' failed", key),
+                    Some(e),
+                )
+            });
+        }
+        Ok(value)
+    }
+
+    // Encode the value of key for store if it matches an encode pattern and
+    // is not encoded yet.
+    pub(crate) fn encode_value(&self, key: &str, value: &str) -> Result<Option<String>> {
+        let prefix = match self
+            .codecs
+            .encoded
+            .iter()
+            .find(|(pattern, _, scope)| glob_match(pattern, &scope.original(key)))
+        {
+            Some((_, prefix, _)) => prefix,
+            None => return Ok(None),
+        };
+        let is_encoded = self.codecs.codecs.iter().any(|(p, s, _)| {
+            value.len() >= p.len() + s.len()
+                && value.starts_with(p.as_str())
+                && value.ends_with(s.as_str())
+        });
+        if is_encoded {
+            return Ok(None);
+        }
+        let (prefix, suffix, codec) = self
+            .codecs
+            .codecs
+            .iter()
+            .find(|(p, _, _)| p == prefix)
+            .ok_or_else(|| PropertiesError::new(format!("no codec registered for '{}'", prefix)))?;
+        let encoded = codec.encode(value).map_err(|e| {
+            PropertiesError::with_cause(format!("encode value of key '{}' failed", key), Some(e))
+        })?;
+        Ok(Some(format!("{}{}{}", prefix, encoded, suffix)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Base64Codec, Properties, ValueCodec};
+    use crate::{global, WriteOption};
+
+    #[test]
+    fn base64() {
+        let cases = vec![
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("s3cret 你好", "czNjcmV0IOS9oOWlvQ=="),
+        ];
+        for &(plain, encoded) in &cases {
+            assert_eq!(Base64Codec.encode(plain).unwrap(), encoded);
+            assert_eq!(Base64Codec.decode(encoded).unwrap(), plain);
+        }
+        for encoded in &["Zg=", "Z===", "Zg==Zg==", "Zm9*"] {
+            if Base64Codec.decode(encoded).is_ok() {
+                panic!("'{}' should be invalid", encoded);
+            }
+        }
+    }
+
+    #[test]
+    fn decode() {
+        let mut prop = Properties::new();
+        prop.load("db.password=ENC(czNjcmV0)\ndb.user=ENC(\nbroken=ENC(***)\n".as_bytes())
+            .unwrap();
+        assert_eq!(prop.get("db.password").unwrap(), "ENC(czNjcmV0)");
+
+        prop.codec("ENC(", ")", Base64Codec);
+        assert_eq!(prop.get("db.password").unwrap(), "s3cret");
+        assert_eq!(prop.get("db.user").unwrap(), "ENC(");
+        assert_eq!(prop.get("broken"), None);
+        for result in [
+            prop.require("broken"),
+            prop.require_all(&["broken"]).map(|_| String::new()),
+        ] {
+            match result {
+                Ok(_) => panic!("decode should failed"),
+                Err(e) => {
+                    assert!(format!("{}", e).starts_with("decode value of key 'broken' failed"))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn raw_values() {
+        // a scoped override restores the encoded value
+        global().with(|p| p.codec("ENC(", ")", Base64Codec));
+        global().set("codec.raw.pw", "ENC(czNjcmV0)");
+        {
+            let _pw = global().scoped("codec.raw.pw", Some("tmp"));
+            assert_eq!(global().get("codec.raw.pw").unwrap(), "tmp");
+        }
+        assert_eq!(
+            global().with(|p| p.get_raw("codec.raw.pw")).unwrap(),
+            "ENC(czNjcmV0)"
+        );
+        assert_eq!(global().get("codec.raw.pw").unwrap(), "s3cret");
+
+        // transactions decode like get, diffs compare the stored values
+        let mut prop = Properties::new();
+        prop.codec("ENC(", ")", Base64Codec);
+        prop.set("pw", "ENC(czNjcmV0)");
+        prop.set("broken", "ENC(***)");
+        let mut other = Properties::new();
+        other.set("pw", "ENC(YWJj)");
+        let diff = prop.diff(&mut other);
+        prop.transaction(|tx| {
+            assert_eq!(tx.get("pw").unwrap(), "s3cret");
+            assert_eq!(tx.get("broken"), None);
+            assert!(tx.require("broken").is_err());
+            tx.set("pw", "ENC(eHl6)");
+            assert_eq!(tx.get("pw").unwrap(), "xyz");
+            assert_eq!(tx.remove("broken").unwrap(), "ENC(***)");
+            Ok::<_, crate::PropertiesError>(())
+        })
+        .unwrap();
+        if prop.apply_diff(&diff).is_ok() {
+            panic!("apply should conflict");
+        }
+        prop.set("pw", "ENC(czNjcmV0)");
+        prop.apply_diff(&diff).unwrap();
+        assert_eq!(prop.get("pw").unwrap(), "abc");
+    }
+
+    #[test]
+    fn encode() {
+        let mut prop = Properties::new();
+        prop.codec("ENC(", ")", Base64Codec);
+        prop.encode_pattern("*.password", "ENC(");
+        prop.set("db.password", "s3cret");
+        prop.set("api.password", "ENC(YWJj)");
+        prop.set("db.user", "sa");
+
+        let mut out = Vec::new();
+        prop.store(&mut out, &WriteOption::default()).unwrap();
+        let mut lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "api.password=ENC(YWJj)",
+                "db.password=ENC(czNjcmV0)",
+                "db.user=sa"
+            ]
+        );
+
+        prop.encode_pattern("db.user", "SECRET(");
+        if prop.store(Vec::new(), &WriteOption::default()).is_ok() {
+            panic!("store should failed without codec");
+        }
+    }
+}
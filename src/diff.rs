@@ -0,0 +1,294 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, BufReader, Read, Write};
+
+use super::redact::MASK;
+use super::writer::save_convert;
+use super::{Change, Properties, PropertiesError, Result};
+
+/// The differences between two `Properties`, ordered by key. Every entry is
+/// a `Change` whose old value is missing for an added key and whose new
+/// value is missing for a removed key.
+///
+/// A diff is serialised as a patch with one line per value, `+key=value` for
+/// the new and `-key=value` for the old value, so a changed key has both.
+/// Keys and values are escaped as in `store`.
+///
+/// The patch holds the real values to be applicable, while the `Debug`
+/// output masks the keys redacted in the compared properties.
+#[derive(Clone, Default)]
+pub struct Diff {
+    changes: Vec<Change>,
+    redacted: HashSet<String>,
+}
+
+/// Diffs with the same changes are equal.
+impl PartialEq for Diff {
+    fn eq(&self, other: &Self) -> bool {
+        self.changes == other.changes
+    }
+}
+
+impl Eq for Diff {}
+
+impl Debug for Diff {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        let mask = |key: &str, v: Option<&str>| match v {
+            Some(_) if self.redacted.contains(key) => Some(MASK.to_string()),
+            v => v.map(String::from),
+        };
+        let changes: Vec<Change> = self
+            .changes
+            .iter()
+            .map(|c| {
+                Change::new(
+                    c.key().to_string(),
+                    mask(c.key(), c.old_value()),
+                    mask(c.key(), c.new_value()),
+                )
+            })
+            .collect();
+        fmt.debug_struct("Diff").field("changes", &changes).finish()
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn added(&self) -> Vec<&Change> {
+        self.changes
+            .iter()
+            .filter(|c| c.old_value().is_none())
+            .collect()
+    }
+
+    pub fn removed(&self) -> Vec<&Change> {
+        self.changes
+            .iter()
+            .filter(|c| c.new_value().is_none())
+            .collect()
+    }
+
+    pub fn changed(&self) -> Vec<&Change> {
+        self.changes
+            .iter()
+            .filter(|c| c.old_value().is_some() && c.new_value().is_some())
+            .collect()
+    }
+
+    /// Writes the diff as a patch.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        for change in &self.changes {
+            let key = save_convert(&change.key().to_string(), true, false)?;
+            for (sign, val) in [(b'-', change.old_value()), (b'+', change.new_value())] {
+                if let Some(val) = val {
+                    writer.write_all(&[sign])?;
+                    writer.write_all(&key)?;
+                    writer.write_all(b"=")?;
+                    writer.write_all(&save_convert(&val.to_string(), false, false)?)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a patch written by `write`, blank lines and lines starting with
+    /// '#' are ignored.
+    pub fn read<R: Read>(reader: R) -> Result<Diff> {
+        let mut changes: Vec<Change> = Vec::new();
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || PropertiesError::new(format!("invalid patch line {}", number + 1));
+            let (sign, entry) = match line.split_at_checked(1) {
+                Some((sign, entry)) if sign == "-" || sign == "+" => (sign, entry),
+                _ => return Err(invalid()),
+            };
+            let mut prop = Properties::new();
+            prop.load(entry.as_bytes())?;
+            let (key, val) = match prop.data.lock().unwrap().drain().next() {
+                Some(kv) => kv,
+                None => return Err(invalid()),
+            };
+
+            // "+key" right after "-key" completes a change
+            match changes.last_mut() {
+                Some(last) if sign == "+" && last.key() == key && last.new_value().is_none() => {
+                    let old = last.old_value().map(String::from);
+                    *last = Change::new(key, old, Some(val));
+                }
+                _ if sign == "-" => changes.push(Change::new(key, Some(val), None)),
+                _ => changes.push(Change::new(key, None, Some(val))),
+            }
+        }
+        Ok(Diff {
+            changes,
+            redacted: HashSet::new(),
+        })
+    }
+}
+
+impl Properties {
+    /// Returns the changes turning these properties into `other`.
+    pub fn diff(&mut self, other: &mut Properties) -> Diff {
+        let ours = self.data.lock().unwrap();
+        let theirs = other.data.lock().unwrap();
+        let keys: BTreeSet<&String> = ours.keys().chain(theirs.keys()).collect();
+        let changes: Vec<Change> = keys
+            .into_iter()
+            .map(|k| Change::new(k.clone(), ours.get(k).cloned(), theirs.get(k).cloned()))
+            .filter(|c| c.old_value() != c.new_value())
+            .collect();
+        let redacted = changes
+            .iter()
+            .filter(|c| self.is_redacted(c.key()) || other.is_redacted(c.key()))
+            .map(|c| c.key().to_string())
+            .collect();
+        Diff { changes, redacted }
+    }
+
+    /// Applies `diff` to these properties as a transaction. The current value
+    /// of every key must be the old value in the diff, or already the new
+    /// one, otherwise nothing is changed and all the conflicts are reported,
+    /// with the redacted values masked.
+    pub fn apply_diff(&mut self, diff: &Diff) -> Result<()> {
+        let redacted: HashSet<&str> = diff
+            .changes()
+            .iter()
+            .map(|c| c.key())
+            .filter(|k| self.is_redacted(k) || diff.redacted.contains(*k))
+            .collect();
+        self.transaction(|tx| {
+            let mut conflicts = Vec::new();
+            for change in diff.changes() {
+                let current = tx.get_raw(change.key());
+                if current.as_deref() == change.new_value() {
+                    continue;
+                }
+                if current.as_deref() != change.old_value() {
+                    let show = |v: Option<&str>| match v {
+                        Some(_) if redacted.contains(change.key()) => format!("'{}'", MASK),
+                        Some(v) => format!("'{}'", v),
+                        None => "<none>".to_string(),
+                    };
+                    conflicts.push(format!(
+                        "'{}' expected {} but found {}",
+                        change.key(),
+                        show(change.old_value()),
+                        show(current.as_deref())
+                    ));
+                    continue;
+                }
+                match change.new_value() {
+                    Some(v) => tx.set(change.key(), v),
+                    None => {
+                        tx.remove(change.key());
+                    }
+                }
+            }
+            if !conflicts.is_empty() {
+                return Err(PropertiesError::new(format!(
+                    "patch conflicts on keys {}",
+                    conflicts.join(", ")
+                )));
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Diff;
+    use crate::testutil::props;
+
+    #[test]
+    fn diff() {
+        let mut base = props("a=1\nb=2\nc=3\n");
+        let mut other = props("a=1\nb=22\nd=4\n");
+        let diff = base.diff(&mut other);
+        let keys = |changes: Vec<&crate::Change>| -> Vec<String> {
+            changes.iter().map(|c| c.key().to_string()).collect()
+        };
+        assert_eq!(keys(diff.added()), vec!["d"]);
+        assert_eq!(keys(diff.removed()), vec!["c"]);
+        assert_eq!(keys(diff.changed()), vec!["b"]);
+        assert!(base.diff(&mut props("c=3\nb=2\na=1")).is_empty());
+    }
+
+    #[test]
+    fn patch() {
+        let mut base = props("a=1\nb=x y\nc=3\n");
+        let mut other = props("a=1\nb=\\ new = value\\n\nd\\ key=4\n");
+        let diff = base.diff(&mut other);
+
+        let mut out = Vec::new();
+        diff.write(&mut out).unwrap();
+        let patch = String::from_utf8(out).unwrap();
+        assert_eq!(patch, "-b=x y\n+b=\\ new \\= value\\n\n-c=3\n+d\\ key=4\n");
+        let parsed = Diff::read(format!("# release 2\n\n{}", patch).as_bytes()).unwrap();
+        assert_eq!(parsed, diff);
+
+        base.apply_diff(&parsed).unwrap();
+        assert!(base.diff(&mut other).is_empty());
+        // already applied
+        base.apply_diff(&parsed).unwrap();
+
+        for line in &["b=1", "*b=1", "+"] {
+            if Diff::read(line.as_bytes()).is_ok() {
+                panic!("patch '{}' should be invalid", line);
+            }
+        }
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut base = props("a=1\nb=2\n");
+        let diff = base.diff(&mut props("a=11\nb=22\nc=33\n"));
+
+        let mut target = props("a=1\nb=20\nc=30\n");
+        match target.apply_diff(&diff) {
+            Ok(_) => panic!("apply should failed"),
+            Err(e) => assert_eq!(
+                format!("{}", e),
+                "patch conflicts on keys 'b' expected '2' but found '20', 'c' expected <none> but found '30'"
+            ),
+        }
+        // nothing is applied
+        assert_eq!(target.get("a").unwrap(), "1");
+    }
+
+    #[test]
+    fn redacted() {
+        let mut base = props("a=1\npassword=s3cret\n");
+        base.redact_pattern("*password*");
+        let diff = base.diff(&mut props("a=2\npassword=t0ken\n"));
+        let debug = format!("{:?}", diff);
+        assert!(
+            !debug.contains("s3cret") && !debug.contains("t0ken"),
+            "{}",
+            debug
+        );
+        assert!(debug.contains("\"2\""), "{}", debug);
+
+        let mut target = props("a=1\npassword=other\n");
+        target.redact_pattern("*password*");
+        match target.apply_diff(&diff) {
+            Ok(_) => panic!("apply should failed"),
+            Err(e) => assert_eq!(
+                format!("{}", e),
+                "patch conflicts on keys 'password' expected '******' but found '******'"
+            ),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use super::query::glob_match;
+use super::{Properties, PropertiesError, ReadOption, Result};
+
+impl Properties {
+    /// Loads every file in the directory `path` whose name matches the glob
+    /// `pattern` (e.g. `*.properties`) in lexical order of the names, so the
+    /// later files override the earlier ones. Origins are recorded.
+    ///
+    /// A missing directory is not an error and loads nothing. Returns the
+    /// files loaded, any error carries the path of the failing file.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, path: P, pattern: &str) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(PropertiesError::from(e).with_path(path)),
+        };
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|e| PropertiesError::from(e).with_path(path))?;
+            let file = entry.path();
+            let matched = match entry.file_name().to_str() {
+                Some(name) => glob_match(pattern, name),
+                None => false,
+            };
+            if matched && file.is_file() {
+                files.push(file);
+            }
+        }
+        files.sort();
+
+        let mut opt = ReadOption::default();
+        opt.origins(true);
+        self.batch(|p| -> Result<()> {
+            for file in &files {
+                p.load_file(file, &opt)?;
+            }
+            Ok(())
+        })?;
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Properties;
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn load_dir() {
+        let dir = TempPath::new("dir");
+        fs::create_dir_all(dir.join("nested.properties")).unwrap();
+        fs::write(dir.join("10-base.properties"), "a=1\nb=1\n").unwrap();
+        fs::write(dir.join("20-override.properties"), "b=2\nc=2\n").unwrap();
+        fs::write(dir.join("05-ignored.conf"), "a=0\nd=0\n").unwrap();
+
+        let mut prop = Properties::new();
+        let files = prop.load_dir(&dir, "*.properties").unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("10-base.properties"),
+                dir.join("20-override.properties")
+            ]
+        );
+        assert_eq!(prop.len(), 3);
+        assert_eq!(prop.get("a").unwrap(), "1");
+        assert_eq!(prop.get("b").unwrap(), "2");
+        assert_eq!(prop.get("c").unwrap(), "2");
+        let origin = prop.origin("b").unwrap();
+        assert_eq!(
+            origin.source().unwrap(),
+            dir.join("20-override.properties").display().to_string()
+        );
+
+        // missing directories are optional
+        let files = prop.load_dir(dir.join("missing"), "*.properties").unwrap();
+        assert!(files.is_empty());
+
+        // errors carry the path of the failing file
+        fs::write(dir.join("30-broken.properties"), "a=\\uzzzz\n").unwrap();
+        match prop.load_dir(&dir, "*.properties") {
+            Ok(_) => panic!("load should failed"),
+            Err(e) => {
+                assert_eq!(e.path(), Some(dir.join("30-broken.properties").as_path()));
+                let msg = format!("{}", e);
+                assert!(
+                    msg.contains("30-broken.properties: parse unicode failed"),
+                    "{}",
+                    msg
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::fmt::{Display, Formatter};
+
+use super::writer::save_convert;
+use super::{Properties, PropertiesError, Result};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Line {
+    // blank lines, comments or anything else kept as is
+    Raw(String),
+    // a logical line, the raw text includes the continuation lines and the
+    // line terminator
+    Entry {
+        key: String,
+        value: String,
+        raw: String,
+    },
+}
+
+impl Line {
+    fn raw(&self) -> &str {
+        match self {
+            Line::Raw(raw) => raw,
+            Line::Entry { raw, .. } => raw,
+        }
+    }
+
+    fn ending(&self) -> &str {
+        let raw = self.raw();
+        &raw[strip_terminator(raw).len()..]
+    }
+}
+
+// Split text into physical lines, each with its terminator.
+fn physical_lines(text: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                result.push(&text[start..i + 1]);
+                start = i + 1;
+            }
+            b'\r' if bytes.get(i + 1) != Some(&b'\n') => {
+                result.push(&text[start..i + 1]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if start < text.len() {
+        result.push(&text[start..]);
+    }
+    result
+}
+
+fn strip_terminator(line: &str) -> &str {
+    line.trim_end_matches(['\n', '\r'])
+}
+
+// A line is continued when it ends with an odd number of backslashes.
+fn is_continued(line: &str) -> bool {
+    let trailing = strip_terminator(line)
+        .bytes()
+        .rev()
+        .take_while(|&c| c == b'\\')
+        .count();
+    trailing % 2 == 1
+}
+
+fn is_raw(line: &str) -> bool {
+    let text = line.trim_start_matches([' ', '\t', '\x0c']);
+    let text = strip_terminator(text);
+    text.is_empty() || text.starts_with('#') || text.starts_with('!')
+}
+
+/// A properties file which keeps its layout, i.e. comments, blank lines, the
+/// order of the keys and the formatting of the untouched entries, so that it
+/// could be edited and written back with minimal changes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Document {
+    lines: Vec<Line>,
+    line_ending: &'static str,
+}
+
+impl Document {
+    /// Parses `text`, keys and values are unescaped as in `load`.
+    pub fn parse(text: &str) -> Result<Document> {
+        let line_ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut lines = Vec::new();
+        let mut physical = physical_lines(text).into_iter();
+        while let Some(line) = physical.next() {
+            if is_raw(line) {
+                lines.push(Line::Raw(line.to_string()));
+                continue;
+            }
+            let mut raw = line.to_string();
+            let mut last = line;
+            while is_continued(last) {
+                match physical.next() {
+                    Some(next) => {
+                        raw.push_str(next);
+                        last = next;
+                    }
+                    None => break,
+                }
+            }
+
+            let mut prop = Properties::new();
+            prop.load(raw.as_bytes())?;
+            let entry = prop.data.lock().unwrap().drain().next();
+            match entry {
+                Some((key, value)) => lines.push(Line::Entry { key, value, raw }),
+                None => lines.push(Line::Raw(raw)),
+            }
+        }
+        Ok(Document { lines, line_ending })
+    }
+
+    /// Same as `parse`, for UTF-8 encoded bytes.
+    pub fn parse_bytes(data: &[u8]) -> Result<Document> {
+        let text = std::str::from_utf8(data)
+            .map_err(|e| PropertiesError::with_cause("invalid utf8 encoding", Some(Box::new(e))))?;
+        Document::parse(text)
+    }
+
+    // Index of the last entry of key, which is the effective one.
+    fn position(&self, key: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|l| matches!(l, Line::Entry { key: k, .. } if k == key))
+    }
+
+    fn render(&self, key: &str, value: &str, ending: &str) -> Result<String> {
+        let mut raw = String::from_utf8(save_convert(&key.to_string(), true, false)?)?;
+        raw.push('=');
+        raw.push_str(&String::from_utf8(save_convert(
+            &value.to_string(),
+            false,
+            false,
+        )?)?);
+        raw.push_str(ending);
+        Ok(raw)
+    }
+
+    /// Returns the keys in the order of the document, without duplicates.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut result: Vec<&str> = Vec::new();
+        for line in &self.lines {
+            if let Line::Entry { key, .. } = line {
+                if !result.contains(&key.as_str()) {
+                    result.push(key);
+                }
+            }
+        }
+        result
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match &self.lines[self.position(key)?] {
+            Line::Entry { value, .. } => Some(value),
+            Line::Raw(_) => None,
+        }
+    }
+
+    /// Sets `key`, an existing entry is rewritten in place, otherwise the
+    /// entry is appended at the end.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match self.position(key) {
+            Some(index) => {
+                let raw = self.render(key, value, self.lines[index].ending())?;
+                self.lines[index] = Line::Entry {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    raw,
+                };
+                Ok(())
+            }
+            None => self.insert(self.lines.len(), key, value),
+        }
+    }
+
+    /// Inserts an entry for `key` after the effective entry of `after`, or at
+    /// the end if `after` is missing.
+    pub fn set_after(&mut self, after: &str, key: &str, value: &str) -> Result<()> {
+        if self.position(key).is_some() {
+            return self.set(key, value);
+        }
+        match self.position(after) {
+            Some(index) => self.insert(index + 1, key, value),
+            None => self.insert(self.lines.len(), key, value),
+        }
+    }
+
+    fn insert(&mut self, index: usize, key: &str, value: &str) -> Result<()> {
+        self.terminate(index);
+        let raw = self.render(key, value, self.line_ending)?;
+        self.lines.insert(
+            index,
+            Line::Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+                raw,
+            },
+        );
+        Ok(())
+    }
+
+    // Make sure the line before index ends with a line terminator, so that
+    // a line could be inserted at index.
+    fn terminate(&mut self, index: usize) {
+        if index == 0 {
+            return;
+        }
+        let ending = self.line_ending;
+        let raw = match &mut self.lines[index - 1] {
+            Line::Raw(raw) => raw,
+            Line::Entry { raw, .. } => raw,
+        };
+        if strip_terminator(raw).len() == raw.len() {
+            raw.push_str(ending);
+        }
+    }
+
+    /// Inserts raw text, e.g. a comment, before the effective entry of `key`,
+    /// or at the end if it's missing. The text must end with a line
+    /// terminator unless it's the last line.
+    pub fn insert_raw(&mut self, key: Option<&str>, text: &str) {
+        let index = key
+            .and_then(|k| self.position(k))
+            .unwrap_or(self.lines.len());
+        self.terminate(index);
+        self.lines.insert(index, Line::Raw(text.to_string()));
+    }
+
+    /// Removes every entry of `key`, returns the effective value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let value = self.get(key).map(String::from);
+        self.lines
+            .retain(|l| !matches!(l, Line::Entry { key: k, .. } if k == key));
+        value
+    }
+
+    /// Renames every entry of `from` to `to` in place keeping the values, an
+    /// existing `to` is removed. Returns whether `from` exists.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<bool> {
+        if self.position(from).is_none() {
+            return Ok(false);
+        }
+        if from != to {
+            self.remove(to);
+        }
+        for index in 0..self.lines.len() {
+            let value = match &self.lines[index] {
+                Line::Entry { key, value, .. } if key == from => value.clone(),
+                _ => continue,
+            };
+            let raw = self.render(to, &value, self.lines[index].ending())?;
+            self.lines[index] = Line::Entry {
+                key: to.to_string(),
+                value,
+                raw,
+            };
+        }
+        Ok(true)
+    }
+
+    /// Returns the effective entries as `Properties`.
+    pub fn to_properties(&self) -> Properties {
+        let mut prop = Properties::new();
+        for line in &self.lines {
+            if let Line::Entry { key, value, .. } = line {
+                prop.set(key, value);
+            }
+        }
+        prop
+    }
+
+    pub(crate) fn line_ending(&self) -> &'static str {
+        self.line_ending
+    }
+}
+
+impl Display for Document {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            write!(fmt, "{}", line.raw())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Document;
+
+    const TEXT: &str = "# database\ndb.url = jdbc:h2:mem\ndb.hosts=a,\\\n    b,\\\n    c\n\n! server\nserver.port:8080\\\\\nmsg=hello";
+
+    #[test]
+    fn parse() {
+        let doc = Document::parse(TEXT).unwrap();
+        assert_eq!(format!("{}", doc), TEXT);
+        assert_eq!(doc.keys(), vec!["db.url", "db.hosts", "server.port", "msg"]);
+        assert_eq!(doc.get("db.hosts").unwrap(), "a,b,c");
+        assert_eq!(doc.get("server.port").unwrap(), "8080\\");
+        assert_eq!(doc.to_properties().len(), 4);
+
+        let doc = Document::parse("a=1\r\n#x\r\nb=2\r\n").unwrap();
+        assert_eq!(doc.line_ending(), "\r\n");
+        assert_eq!(format!("{}", doc), "a=1\r\n#x\r\nb=2\r\n");
+    }
+
+    #[test]
+    fn edit() {
+        let mut doc = Document::parse(TEXT).unwrap();
+        doc.set("db.hosts", "d").unwrap();
+        doc.set("new key", "x").unwrap();
+        doc.set_after("db.url", "db.user", "sa").unwrap();
+        doc.remove("server.port");
+        doc.rename("msg", "message").unwrap();
+        doc.insert_raw(Some("db.user"), "# user\n");
+        assert_eq!(
+            format!("{}", doc),
+            "# database\ndb.url = jdbc:h2:mem\n# user\ndb.user=sa\ndb.hosts=d\n\n! server\nmessage=hello\nnew\\ key=x\n"
+        );
+    }
+}
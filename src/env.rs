@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use super::Properties;
+
+/// Naming convention between keys and environment variables, by default
+/// `db.max-size` with prefix `APP` maps to `APP_DB_MAX__SIZE`: the prefix and
+/// '_' are prepended, '.' becomes '_', '-' becomes '__' and the name is upper
+/// cased.
+pub struct EnvNaming {
+    prefix: String,
+    upper_case: bool,
+    dot: String,
+    dash: String,
+}
+
+impl EnvNaming {
+    pub fn new<S: Into<String>>(prefix: S) -> Self {
+        Self {
+            prefix: prefix.into(),
+            upper_case: true,
+            dot: "_".to_string(),
+            dash: "__".to_string(),
+        }
+    }
+
+    pub fn upper_case(&mut self, val: bool) -> &Self {
+        self.upper_case = val;
+        self
+    }
+
+    /// Sets the separator used in variables for '.' in keys.
+    pub fn dot(&mut self, val: String) -> &Self {
+        self.dot = val;
+        self
+    }
+
+    /// Sets the separator used in variables for '-' in keys.
+    pub fn dash(&mut self, val: String) -> &Self {
+        self.dash = val;
+        self
+    }
+
+    /// Returns the key for variable `name`, None if the name doesn't start
+    /// with the prefix.
+    pub fn to_key(&self, name: &str) -> Option<String> {
+        let rest = if self.prefix.is_empty() {
+            name
+        } else {
+            name.strip_prefix(&self.prefix)?.strip_prefix('_')?
+        };
+        if rest.is_empty() {
+            return None;
+        }
+        // match the longer separator first, as it may contain the other one
+        let key = if self.dash.len() >= self.dot.len() {
+            rest.replace(&self.dash, "-").replace(&self.dot, ".")
+        } else {
+            rest.replace(&self.dot, ".").replace(&self.dash, "-")
+        };
+        if self.upper_case {
+            Some(key.to_lowercase())
+        } else {
+            Some(key)
+        }
+    }
+
+    /// Returns the variable name for `key`.
+    pub fn to_var(&self, key: &str) -> String {
+        let name = key.replace('-', &self.dash).replace('.', &self.dot);
+        let name = if self.upper_case {
+            name.to_uppercase()
+        } else {
+            name
+        };
+        if self.prefix.is_empty() {
+            name
+        } else {
+            format!("{}_{}", self.prefix, name)
+        }
+    }
+}
+
+impl Properties {
+    /// Sets every environment variable starting with `prefix` and '_' with
+    /// the default `EnvNaming`, e.g. `APP_DB_URL` as `db.url`. Returns the
+    /// number of variables applied.
+    pub fn overlay_env(&mut self, prefix: &str) -> usize {
+        self.overlay_env_with(&EnvNaming::new(prefix))
+    }
+
+    /// Same as `overlay_env`, with a custom naming convention. Variables
+    /// whose name or value is not valid UTF-8 are skipped.
+    pub fn overlay_env_with(&mut self, naming: &EnvNaming) -> usize {
+        let vars = std::env::vars_os()
+            .filter_map(|(name, val)| Some((name.into_string().ok()?, val.into_string().ok()?)));
+        self.overlay_vars(vars, naming)
+    }
+
+    /// Sets the `(name, value)` variables matching `naming`, returns the
+    /// number of variables applied.
+    pub fn overlay_vars<I>(&mut self, vars: I, naming: &EnvNaming) -> usize
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        self.batch(|p| {
+            let mut count = 0;
+            for (name, val) in vars {
+                if let Some(key) = naming.to_key(&name) {
+                    p.set(&key, &val);
+                    count += 1;
+                }
+            }
+            count
+        })
+    }
+
+    /// Returns all the properties as environment variables, the reverse of
+    /// `overlay_env_with`.
+    pub fn to_env(&mut self, naming: &EnvNaming) -> BTreeMap<String, String> {
+        let data = self.data.lock().unwrap();
+        data.iter()
+            .map(|(k, v)| (naming.to_var(k), v.clone()))
+            .collect()
+    }
+
+    /// Passes all the properties to a child process as environment variables.
+    pub fn apply_env(&mut self, cmd: &mut Command, naming: &EnvNaming) {
+        cmd.envs(self.to_env(naming));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnvNaming, Properties};
+
+    #[test]
+    fn naming() {
+        let mut custom = EnvNaming::new("");
+        custom.upper_case(false);
+        custom.dot("__".to_string());
+        custom.dash("_".to_string());
+
+        let cases = vec![
+            (EnvNaming::new("APP"), "APP_DB_URL", Some("db.url")),
+            (
+                EnvNaming::new("APP"),
+                "APP_DB_MAX__SIZE",
+                Some("db.max-size"),
+            ),
+            (EnvNaming::new("APP"), "APPX_DB_URL", None),
+            (EnvNaming::new("APP"), "APP_", None),
+            (EnvNaming::new(""), "HOME", Some("home")),
+            (custom, "db__max_size", Some("db.max-size")),
+        ];
+        for (naming, name, key) in &cases {
+            assert_eq!(naming.to_key(name).as_deref(), *key, "{}", name);
+            if let Some(key) = key {
+                assert_eq!(naming.to_var(key), *name);
+            }
+        }
+    }
+
+    #[test]
+    fn overlay() {
+        let vars = vec![
+            ("APP_DB_URL", "jdbc:pg://db"),
+            ("APP_POOL_MAX__SIZE", "16"),
+            ("HOME", "/root"),
+        ];
+        let mut prop = Properties::new();
+        prop.set("db.url", "jdbc:h2:mem");
+        let count = prop.overlay_vars(
+            vars.into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string())),
+            &EnvNaming::new("APP"),
+        );
+        assert_eq!(count, 2);
+        assert_eq!(prop.len(), 2);
+        assert_eq!(prop.get("db.url").unwrap(), "jdbc:pg://db");
+        assert_eq!(prop.get("pool.max-size").unwrap(), "16");
+
+        std::env::set_var("PROPS_ENV_TEST_SERVER_PORT", "8080");
+        assert_eq!(prop.overlay_env("PROPS_ENV_TEST"), 1);
+        assert_eq!(prop.get("server.port").unwrap(), "8080");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn overlay_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        std::env::set_var("PROPS_ENV_UTF8_OK", "1");
+        std::env::set_var("PROPS_ENV_UTF8_BAD", OsStr::from_bytes(b"\xff"));
+        std::env::set_var(OsStr::from_bytes(b"PROPS_ENV_UTF8_\xff"), "2");
+        let mut prop = Properties::new();
+        assert_eq!(prop.overlay_env("PROPS_ENV_UTF8"), 1);
+        assert_eq!(prop.get("ok").unwrap(), "1");
+    }
+
+    #[test]
+    fn export() {
+        let mut prop = Properties::new();
+        prop.set("db.url", "jdbc:pg://db");
+        prop.set("pool.max-size", "16");
+
+        let env = prop.to_env(&EnvNaming::new("APP"));
+        let expected = vec![
+            ("APP_DB_URL".to_string(), "jdbc:pg://db".to_string()),
+            ("APP_POOL_MAX__SIZE".to_string(), "16".to_string()),
+        ];
+        assert_eq!(env.into_iter().collect::<Vec<_>>(), expected);
+
+        let mut cmd = std::process::Command::new("env");
+        prop.apply_env(&mut cmd, &EnvNaming::new("APP"));
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(envs.len(), 2);
+    }
+}
@@ -0,0 +1,423 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{Properties, PropertiesError, Result, Subscription, Watcher, WriteOption};
+
+// How often `watch` looks for changes while auto save is disabled.
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+// 64 bit FNV-1a, good enough to notice a file changed behind our back.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in data {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// Read the file, None if it doesn't exist.
+pub(crate) fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(PropertiesError::from(e).with_path(path)),
+    }
+}
+
+// Write to a temporary file next to path and rename it over path, so that
+// readers see either the old or the new content, never a partial one. A
+// symlink is written through to its target and the permissions of an
+// existing file are kept.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) if e.kind() == ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(PropertiesError::from(e).with_path(path)),
+    };
+    let name = target
+        .file_name()
+        .ok_or_else(|| PropertiesError::new("invalid file name").with_path(path))?;
+    let tmp = target.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let permissions = fs::metadata(&target).ok().map(|m| m.permissions());
+    let result = fs::File::create(&tmp)
+        .and_then(|mut f| {
+            if let Some(permissions) = permissions {
+                f.set_permissions(permissions)?;
+            }
+            f.write_all(data)?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, &target));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(PropertiesError::from(e).with_path(path));
+    }
+    Ok(())
+}
+
+/// Properties bound to a file, which keeps track of whether the properties
+/// changed since they were loaded or saved.
+///
+/// Saving fails with a conflict if the file was changed on disk meanwhile,
+/// compared by a hash of its content. `reload` discards the changes made in
+/// memory and picks up the content on disk.
+pub struct PropertiesFile {
+    path: PathBuf,
+    opt: WriteOption,
+    props: Properties,
+    revision: Arc<AtomicU64>,
+    saved: u64,
+    content: u64,
+    disk: Option<u64>,
+    last_change: Arc<Mutex<Option<Instant>>>,
+    auto_save: Option<Duration>,
+    _subscription: Subscription,
+}
+
+impl PropertiesFile {
+    /// Loads the file at `path`, a missing file is treated as empty and is
+    /// created by the first `save`.
+    pub fn open<P: Into<PathBuf>>(path: P, opt: WriteOption) -> Result<Self> {
+        let path = path.into();
+        let mut props = Properties::new();
+        let revision = Arc::new(AtomicU64::new(0));
+        let last_change = Arc::new(Mutex::new(None));
+        let (r, l) = (revision.clone(), last_change.clone());
+        let subscription = props.subscribe_prefix("", move |_| {
+            r.fetch_add(1, Ordering::SeqCst);
+            *l.lock().unwrap() = Some(Instant::now());
+        });
+
+        let mut result = Self {
+            path,
+            opt,
+            props,
+            revision,
+            saved: 0,
+            content: 0,
+            disk: None,
+            last_change,
+            auto_save: None,
+            _subscription: subscription,
+        };
+        result.reload()?;
+        Ok(result)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the properties changed since they were loaded or
+    /// saved. Changes which were undone meanwhile don't count.
+    pub fn is_dirty(&self) -> bool {
+        self.revision.load(Ordering::SeqCst) != self.saved && self.content_hash() != self.content
+    }
+
+    // Hash of the keys and values, independent of their order in the map.
+    fn content_hash(&self) -> u64 {
+        let data = self.props.data.lock().unwrap();
+        let mut entries: Vec<(&String, &String)> = data.iter().collect();
+        entries.sort();
+        let mut bytes = Vec::new();
+        for (k, v) in entries {
+            bytes.extend_from_slice(k.as_bytes());
+            bytes.push(0);
+            bytes.extend_from_slice(v.as_bytes());
+            bytes.push(0);
+        }
+        fnv1a(&bytes)
+    }
+
+    /// Enables auto save, the changes are saved once no further change
+    /// happened for `delay`, either by the thread started with `watch` or by
+    /// calling `tick`, and the pending changes are saved on drop. `None`
+    /// disables it.
+    pub fn auto_save(&mut self, delay: Option<Duration>) {
+        self.auto_save = delay;
+    }
+
+    // Time left until the pending changes are due to be auto saved, None if
+    // there is nothing to save.
+    fn due(&self) -> Option<Duration> {
+        let delay = match self.auto_save {
+            Some(delay) if self.is_dirty() => delay,
+            _ => return None,
+        };
+        match *self.last_change.lock().unwrap() {
+            Some(at) => Some(delay.saturating_sub(at.elapsed())),
+            None => Some(Duration::ZERO),
+        }
+    }
+
+    /// Saves the pending changes if auto save is enabled and the last change
+    /// is older than the delay, returns whether the file was saved. Intended
+    /// to be called periodically, e.g. from an event loop, see `watch` for a
+    /// background thread.
+    pub fn tick(&mut self) -> Result<bool> {
+        if self.due() != Some(Duration::ZERO) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Starts a background thread which auto saves `file` once the changes
+    /// settled for the auto save delay, until the returned `Watcher` is
+    /// dropped. A failed save is retried after the next delay.
+    pub fn watch(file: &Arc<Mutex<PropertiesFile>>) -> Watcher {
+        let file = Arc::clone(file);
+        Watcher::spawn(move |rx| {
+            let mut wait = Duration::ZERO;
+            loop {
+                match rx.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let mut file = file.lock().unwrap();
+                let delay = file.auto_save.unwrap_or(IDLE_INTERVAL);
+                wait = match file.due() {
+                    Some(left) if left.is_zero() => {
+                        let _ = file.save();
+                        delay
+                    }
+                    Some(left) => left,
+                    None => delay,
+                };
+            }
+        })
+    }
+
+    /// Writes the properties to the file atomically. Fails if the file was
+    /// changed on disk since it was loaded or saved, nothing is written then.
+    pub fn save(&mut self) -> Result<()> {
+        let current = read_optional(&self.path)?.map(|data| fnv1a(&data));
+        if current != self.disk {
+            return Err(
+                PropertiesError::new("conflict, file changed on disk since it was loaded")
+                    .with_path(&self.path),
+            );
+        }
+        let revision = self.revision.load(Ordering::SeqCst);
+        let mut data = Vec::new();
+        self.props.store(&mut data, &self.opt)?;
+        write_atomic(&self.path, &data)?;
+        self.disk = Some(fnv1a(&data));
+        self.saved = revision;
+        self.content = self.content_hash();
+        Ok(())
+    }
+
+    /// Loads the file again, discarding any change made in memory. Listeners
+    /// of the properties are notified of the differences.
+    pub fn reload(&mut self) -> Result<()> {
+        let data = read_optional(&self.path)?;
+        let mut fresh = Properties::new();
+        if let Some(data) = &data {
+            fresh
+                .load(data.as_slice())
+                .map_err(|e| e.with_path(&self.path))?;
+        }
+        let fresh = std::mem::take(&mut *fresh.data.lock().unwrap());
+        let stale: Vec<String> = self
+            .props
+            .data
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|k| !fresh.contains_key(*k))
+            .cloned()
+            .collect();
+        self.props.transaction(|tx| {
+            for k in &stale {
+                tx.remove(k);
+            }
+            for (k, v) in &fresh {
+                tx.set(k, v);
+            }
+            Ok::<_, PropertiesError>(())
+        })?;
+
+        self.disk = data.map(|data| fnv1a(&data));
+        self.saved = self.revision.load(Ordering::SeqCst);
+        self.content = self.content_hash();
+        Ok(())
+    }
+}
+
+impl Drop for PropertiesFile {
+    fn drop(&mut self) {
+        if self.auto_save.is_some() && self.is_dirty() {
+            let _ = self.save();
+        }
+    }
+}
+
+impl Deref for PropertiesFile {
+    type Target = Properties;
+
+    fn deref(&self) -> &Properties {
+        &self.props
+    }
+}
+
+impl DerefMut for PropertiesFile {
+    fn deref_mut(&mut self) -> &mut Properties {
+        &mut self.props
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::PropertiesFile;
+    use crate::testutil::TempPath;
+    use crate::{Properties, WriteOption};
+
+    fn read(path: &std::path::Path) -> Properties {
+        let mut prop = Properties::new();
+        prop.load(fs::File::open(path).unwrap()).unwrap();
+        prop
+    }
+
+    #[test]
+    fn save() {
+        let path = TempPath::new("file-save");
+        let mut file = PropertiesFile::open(&path, WriteOption::default()).unwrap();
+        assert!(!file.is_dirty());
+        assert_eq!(file.len(), 0);
+
+        file.set("a", "1");
+        file.set("b", "2");
+        assert!(file.is_dirty());
+        file.save().unwrap();
+        assert!(!file.is_dirty());
+        assert_eq!(read(&path).get("a").unwrap(), "1");
+
+        // setting the same value is not a change
+        file.set("a", "1");
+        assert!(!file.is_dirty());
+
+        // neither is a change which was undone
+        file.set("b", "3");
+        file.remove("a");
+        assert!(file.is_dirty());
+        file.set("b", "2");
+        file.set("a", "1");
+        assert!(!file.is_dirty());
+
+        // discard the changes in memory
+        file.remove("b");
+        file.set("c", "3");
+        file.reload().unwrap();
+        assert!(!file.is_dirty());
+        assert_eq!(file.get("b").unwrap(), "2");
+        assert_eq!(file.get("c"), None);
+    }
+
+    #[test]
+    fn conflict() {
+        let path = TempPath::new("file-conflict");
+        fs::write(&path, "a=1\n").unwrap();
+        let mut file = PropertiesFile::open(&path, WriteOption::default()).unwrap();
+        file.set("a", "2");
+
+        fs::write(&path, "a=3\n").unwrap();
+        match file.save() {
+            Ok(_) => panic!("save should failed"),
+            Err(e) => {
+                let msg = format!("{}", e);
+                assert!(msg.contains("conflict, file changed on disk"), "{}", msg);
+            }
+        }
+        assert_eq!(read(&path).get("a").unwrap(), "3");
+
+        file.reload().unwrap();
+        assert_eq!(file.get("a").unwrap(), "3");
+        file.set("a", "4");
+        file.save().unwrap();
+        assert_eq!(read(&path).get("a").unwrap(), "4");
+    }
+
+    #[test]
+    fn auto_save() {
+        let path = TempPath::new("file-auto");
+        let mut file = PropertiesFile::open(&path, WriteOption::default()).unwrap();
+        assert!(!file.tick().unwrap());
+        file.set("a", "1");
+        // disabled by default
+        assert!(!file.tick().unwrap());
+
+        file.auto_save(Some(Duration::from_millis(50)));
+        file.set("a", "2");
+        assert!(!file.tick().unwrap());
+        assert!(!path.exists());
+        thread::sleep(Duration::from_millis(60));
+        assert!(file.tick().unwrap());
+        assert_eq!(read(&path).get("a").unwrap(), "2");
+
+        // pending changes are saved on drop
+        file.set("a", "3");
+        drop(file);
+        assert_eq!(read(&path).get("a").unwrap(), "3");
+    }
+
+    #[test]
+    fn watch() {
+        let path = TempPath::new("file-watch");
+        let mut file = PropertiesFile::open(&path, WriteOption::default()).unwrap();
+        file.auto_save(Some(Duration::from_millis(20)));
+        let file = Arc::new(Mutex::new(file));
+        let watcher = PropertiesFile::watch(&file);
+
+        file.lock().unwrap().set("a", "1");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !path.exists() {
+            if Instant::now() > deadline {
+                panic!("file is not saved");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(read(&path).get("a").unwrap(), "1");
+        assert!(!file.lock().unwrap().is_dirty());
+        drop(watcher);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = TempPath::new("file-atomic");
+        fs::create_dir(&dir).unwrap();
+        let target = dir.join("target.properties");
+        let link = dir.join("link.properties");
+        fs::write(&target, "a=1\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        symlink(&target, &link).unwrap();
+
+        super::write_atomic(&link, b"a=2\n").unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "a=2\n");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use super::listener::{self, Filter, Listeners};
+use super::{Change, Properties, PropertiesError, Result, Subscription};
+
+/// Process wide properties, like `System.getProperties()` in Java. All the
+/// methods are thread safe, see `global()`.
+pub struct Global {
+    props: Mutex<Properties>,
+    // kept apart from the listeners of props, so that they are called
+    // without props locked and could use global()
+    listeners: Arc<Mutex<Listeners>>,
+    initialized: AtomicBool,
+}
+
+/// Restores the previous value of a key overridden by `Global::scoped` when
+/// dropped.
+pub struct ScopedOverride {
+    key: String,
+    previous: Option<String>,
+}
+
+impl Drop for ScopedOverride {
+    fn drop(&mut self) {
+        match self.previous.take() {
+            Some(val) => global().set(&self.key, &val),
+            None => global().remove(&self.key),
+        };
+    }
+}
+
+/// Returns the process wide properties, empty until `Global::init`.
+pub fn global() -> &'static Global {
+    static GLOBAL: OnceLock<Global> = OnceLock::new();
+    GLOBAL.get_or_init(|| Global {
+        props: Mutex::new(Properties::new()),
+        listeners: Arc::new(Mutex::new(Listeners::default())),
+        initialized: AtomicBool::new(false),
+    })
+}
+
+impl Global {
+    /// Initializes the properties once, e.g. from files, the environment and
+    /// the command line, any later call fails. Nothing is changed if `f`
+    /// fails, and no listener is notified.
+    pub fn init<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Properties) -> Result<()>,
+    {
+        if self.initialized.swap(true, Ordering::SeqCst) {
+            return Err(PropertiesError::new(
+                "global properties already initialized",
+            ));
+        }
+        let mut prop = Properties::new();
+        if let Err(e) = f(&mut prop) {
+            self.initialized.store(false, Ordering::SeqCst);
+            return Err(e);
+        }
+        let loaded = std::mem::take(&mut *prop.data.lock().unwrap());
+        let mut props = self.props.lock().unwrap();
+        props.data.lock().unwrap().extend(loaded);
+        props.origins.extend(prop.origins);
+        Ok(())
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.props.lock().unwrap().get(key)
+    }
+
+    /// Like `System.getProperty(key, def)`.
+    pub fn get_or(&self, key: &str, def: &str) -> String {
+        self.get(key).unwrap_or_else(|| def.to_string())
+    }
+
+    /// Sets `key` and returns the previous value as stored, like
+    /// `System.setProperty`, i.e. aliases and codecs are not applied.
+    pub fn set(&self, key: &str, value: &str) -> Option<String> {
+        let previous = {
+            let mut props = self.props.lock().unwrap();
+            let previous = props.get_raw(key);
+            props.set(key, value);
+            previous
+        };
+        self.notify(key, previous.clone(), Some(value.to_string()));
+        previous
+    }
+
+    pub fn remove(&self, key: &str) -> Option<String> {
+        let previous = self.props.lock().unwrap().remove(key);
+        if previous.is_some() {
+            self.notify(key, previous.clone(), None);
+        }
+        previous
+    }
+
+    /// Runs `f` with the properties locked, e.g. for bulk reads. Changes made
+    /// here are not notified to the listeners.
+    pub fn with<R, F: FnOnce(&mut Properties) -> R>(&self, f: F) -> R {
+        f(&mut self.props.lock().unwrap())
+    }
+
+    /// Overrides `key` until the returned guard is dropped, `None` removes
+    /// the key meanwhile. Intended for tests.
+    pub fn scoped(&self, key: &str, value: Option<&str>) -> ScopedOverride {
+        let previous = match value {
+            Some(val) => self.set(key, val),
+            None => self.remove(key),
+        };
+        ScopedOverride {
+            key: key.to_string(),
+            previous,
+        }
+    }
+
+    /// Registers `f` to be called after every `set` and `remove` of `key`,
+    /// from the thread making the change, until the returned `Subscription`
+    /// is dropped, like `Properties::subscribe`.
+    pub fn subscribe<F>(&self, key: &str, f: F) -> Subscription
+    where
+        F: Fn(&[Change]) + Send + Sync + 'static,
+    {
+        listener::register(&self.listeners, Filter::Key(key.to_string()), Arc::new(f))
+    }
+
+    /// Same as `subscribe`, for `prefix` and every key under it, an empty
+    /// prefix matches every key.
+    pub fn subscribe_prefix<F>(&self, prefix: &str, f: F) -> Subscription
+    where
+        F: Fn(&[Change]) + Send + Sync + 'static,
+    {
+        listener::register(
+            &self.listeners,
+            Filter::Prefix(prefix.to_string()),
+            Arc::new(f),
+        )
+    }
+
+    fn notify(&self, key: &str, old: Option<String>, new: Option<String>) {
+        // called without props locked, so that listeners could use global()
+        if old != new {
+            listener::notify(&self.listeners, &[Change::new(key.to_string(), old, new)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use super::global;
+
+    #[test]
+    fn init() {
+        let result = global().init(|p| {
+            p.load("global.init.a=1\n".as_bytes())?;
+            p.set("global.init.b", "2");
+            Ok(())
+        });
+        // other tests never initialize, so only this one could succeed
+        result.unwrap();
+        assert!(global().is_initialized());
+        assert_eq!(global().get("global.init.a").unwrap(), "1");
+        assert_eq!(global().get_or("global.init.c", "3"), "3");
+
+        if global().init(|_| Ok(())).is_ok() {
+            panic!("init twice should failed");
+        }
+    }
+
+    #[test]
+    fn threads() {
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                thread::spawn(move || {
+                    global().set(&format!("global.threads.{}", i), &i.to_string());
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        for i in 0..8 {
+            assert_eq!(
+                global().get(&format!("global.threads.{}", i)).unwrap(),
+                i.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn scoped() {
+        global().set("global.scoped.a", "1");
+        {
+            let _a = global().scoped("global.scoped.a", Some("2"));
+            let _b = global().scoped("global.scoped.b", Some("2"));
+            assert_eq!(global().get("global.scoped.a").unwrap(), "2");
+            assert_eq!(global().get("global.scoped.b").unwrap(), "2");
+            {
+                let _a = global().scoped("global.scoped.a", None);
+                assert_eq!(global().get("global.scoped.a"), None);
+            }
+            assert_eq!(global().get("global.scoped.a").unwrap(), "2");
+        }
+        assert_eq!(global().get("global.scoped.a").unwrap(), "1");
+        assert_eq!(global().get("global.scoped.b"), None);
+    }
+
+    #[test]
+    fn scoped_alias() {
+        global().with(|p| p.alias("global.alias.old", "global.alias.new"));
+        global().set("global.alias.old", "legacy");
+        {
+            let _new = global().scoped("global.alias.new", Some("x"));
+            assert_eq!(global().get("global.alias.new").unwrap(), "x");
+        }
+        assert_eq!(global().with(|p| p.get_raw("global.alias.new")), None);
+        assert_eq!(global().get("global.alias.new").unwrap(), "legacy");
+    }
+
+    #[test]
+    fn subscribe() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cloned = events.clone();
+        let subscription = global().subscribe_prefix("global.subscribe", move |changes| {
+            for c in changes {
+                let old = c.old_value().map(String::from);
+                let new = c.new_value().map(String::from);
+                cloned.lock().unwrap().push((c.key().to_string(), old, new));
+            }
+        });
+
+        global().set("global.subscribe.a", "1");
+        global().set("global.subscribe.a", "2");
+        global().set("global.subscribe.a", "2");
+        global().remove("global.subscribe.a");
+        global().remove("global.subscribe.a");
+        global().set("global.other", "1");
+        drop(subscription);
+        global().set("global.subscribe.a", "3");
+
+        let a = "global.subscribe.a".to_string();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (a.clone(), None, Some("1".to_string())),
+                (a.clone(), Some("1".to_string()), Some("2".to_string())),
+                (a.clone(), Some("2".to_string()), None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use super::{Properties, PropertiesError, ReadOption, Result};
+
+pub(crate) const INCLUDE: &str = "include";
+pub(crate) const INCLUDE_OPTIONAL: &str = "include.optional";
+
+impl Properties {
+    // Load the comma separated files of an include entry, relative paths are
+    // resolved against the directory of the including file, or the current
+    // directory if it's not loaded from a file.
+    pub(crate) fn include(&mut self, val: &str, optional: bool, opt: &ReadOption) -> Result<()> {
+        for name in val.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let path = match &opt.base_dir {
+                Some(dir) => dir.join(name),
+                None => PathBuf::from(name),
+            };
+            let canonical = match path.canonicalize() {
+                Ok(canonical) => canonical,
+                Err(e) if optional && e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(PropertiesError::with_cause(
+                        format!("include '{}' failed", name),
+                        Some(Box::new(e)),
+                    ))
+                }
+            };
+
+            if let Some(root) = &opt.include_root {
+                let root = root.canonicalize()?;
+                if !canonical.starts_with(&root) {
+                    return Err(PropertiesError::new(format!(
+                        "include '{}' is outside of root '{}'",
+                        name,
+                        root.display()
+                    )));
+                }
+            }
+            if opt.include_stack.contains(&canonical) {
+                return Err(PropertiesError::new(format!(
+                    "include '{}' failed, cycle detected",
+                    name
+                )));
+            }
+            if opt.include_depth >= opt.max_include_depth {
+                return Err(PropertiesError::new(format!(
+                    "include '{}' failed, exceeds max depth {}",
+                    name, opt.max_include_depth
+                )));
+            }
+
+            let mut child = opt.clone();
+            child.source = None;
+            child.include_depth += 1;
+            self.load_file(&canonical, &child)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Properties;
+    use crate::testutil::TempPath;
+    use crate::ReadOption;
+
+    fn create(name: &str, files: &[(&str, &str)]) -> TempPath {
+        let dir = TempPath::new(&format!("include-{}", name));
+        for (file, content) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn options() -> ReadOption {
+        let mut opt = ReadOption::default();
+        opt.includes(true);
+        opt.origins(true);
+        opt
+    }
+
+    #[test]
+    fn include() {
+        let dir = create(
+            "normal",
+            &[
+                ("app.properties", "a=1\ninclude = conf/common.properties, conf/db.properties\nb=1\ninclude.optional=missing.properties\n"),
+                ("conf/common.properties", "a=2\nb=2\nc=2\n"),
+                ("conf/db.properties", "include=../shared/pool.properties\ndb.url=jdbc:h2:mem\n"),
+                ("shared/pool.properties", "pool.size=8\n"),
+            ],
+        );
+
+        let mut prop = Properties::new();
+        prop.load_file(dir.join("app.properties"), &options())
+            .unwrap();
+        let cases = vec![
+            ("a", "2"),
+            ("b", "1"),
+            ("c", "2"),
+            ("db.url", "jdbc:h2:mem"),
+            ("pool.size", "8"),
+        ];
+        assert_eq!(prop.len(), cases.len());
+        for &(key, val) in &cases {
+            assert_eq!(prop.get(key).unwrap(), val, "{}", key);
+        }
+        let origin = prop.origin("pool.size").unwrap();
+        assert!(origin.source().unwrap().ends_with("pool.properties"));
+
+        // disabled by default
+        let mut prop = Properties::new();
+        prop.load_file(dir.join("app.properties"), &ReadOption::default())
+            .unwrap();
+        assert_eq!(
+            prop.get("include").unwrap(),
+            "conf/common.properties, conf/db.properties"
+        );
+    }
+
+    #[test]
+    fn abnormal() {
+        let dir = create(
+            "abnormal",
+            &[
+                ("missing.properties", "include=none.properties\n"),
+                ("cycle.properties", "include=cycle2.properties\n"),
+                ("cycle2.properties", "include=cycle.properties\n"),
+                ("deep.properties", "include=deep1.properties\n"),
+                ("deep1.properties", "include=deep2.properties\n"),
+                ("deep2.properties", "a=1\n"),
+                ("sub/escape.properties", "include=../deep2.properties\n"),
+            ],
+        );
+        let mut max_depth = options();
+        max_depth.max_include_depth(1);
+        let mut sandbox = options();
+        sandbox.include_root(dir.join("sub"));
+
+        let cases = vec![
+            (
+                "missing.properties",
+                options(),
+                "include 'none.properties' failed",
+            ),
+            (
+                "cycle.properties",
+                options(),
+                "include 'cycle.properties' failed, cycle detected",
+            ),
+            (
+                "deep.properties",
+                max_depth,
+                "include 'deep2.properties' failed, exceeds max depth 1",
+            ),
+            (
+                "sub/escape.properties",
+                sandbox,
+                "include '../deep2.properties' is outside of root",
+            ),
+        ];
+        for (file, opt, err) in &cases {
+            let mut prop = Properties::new();
+            match prop.load_file(dir.join(file), opt) {
+                Ok(_) => panic!("load {} should failed", file),
+                Err(e) => {
+                    let msg = format!("{}", e);
+                    assert!(msg.contains(err), "{}", msg);
+                }
+            }
+        }
+
+        let mut prop = Properties::new();
+        prop.load_file(dir.join("sub/escape.properties"), &options())
+            .unwrap();
+        assert_eq!(prop.get("a").unwrap(), "1");
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use super::writer::save_convert;
+use super::{Change, Properties, PropertiesError, Result};
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Edit,
+    Undo,
+    Redo,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Edit => "edit",
+            Kind::Undo => "undo",
+            Kind::Redo => "redo",
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Journal {
+    // the operations which could be undone or redone, entries[..position]
+    // are applied
+    entries: Vec<Vec<Change>>,
+    position: usize,
+    checkpoints: HashMap<String, usize>,
+    // everything applied in order, including undo and redo
+    trail: Vec<(Kind, Vec<Change>)>,
+}
+
+impl Journal {
+    fn record(&mut self, changes: &[Change]) {
+        self.entries.truncate(self.position);
+        let position = self.position;
+        self.checkpoints.retain(|_, p| *p <= position);
+        self.entries.push(changes.to_vec());
+        self.position += 1;
+        self.trail.push((Kind::Edit, changes.to_vec()));
+    }
+}
+
+impl Properties {
+    /// Enables or disables the journal. While enabled, every operation
+    /// changing the properties (`set`, `remove`, `load`, a transaction, ...)
+    /// is recorded and could be reverted with `undo`, enabling it again
+    /// starts over with an empty journal.
+    pub fn journal(&mut self, val: bool) {
+        self.journal = if val { Some(Journal::default()) } else { None };
+    }
+
+    // Record an operation which has just been applied.
+    pub(crate) fn record_journal(&mut self, changes: &[Change]) {
+        if let Some(journal) = self.journal.as_mut() {
+            if !changes.is_empty() {
+                journal.record(changes);
+            }
+        }
+    }
+
+    // Apply the changes without recording them, listeners are notified as
+    // usual.
+    fn replay(&mut self, kind: Kind, changes: Vec<Change>) {
+        let journal = self.journal.take();
+        let mut data = self.data.lock().unwrap();
+        for change in &changes {
+            match change.new_value() {
+                Some(v) => data.insert(change.key().to_string(), v.to_string()),
+                None => data.remove(change.key()),
+            };
+            self.origins.remove(change.key());
+        }
+        drop(data);
+        self.batch(|p| {
+            for change in &changes {
+                p.record_change(change.clone());
+            }
+        });
+        self.journal = journal;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.trail.push((kind, changes));
+        }
+    }
+
+    /// Reverts the last operation recorded by the journal, returns false if
+    /// there is nothing to undo or the journal is disabled.
+    pub fn undo(&mut self) -> bool {
+        let changes = match self.journal.as_mut() {
+            Some(j) if j.position > 0 => {
+                j.position -= 1;
+                j.entries[j.position].clone()
+            }
+            _ => return false,
+        };
+        let reverted = changes
+            .into_iter()
+            .rev()
+            .map(|c| {
+                let old = c.old_value().map(String::from);
+                let new = c.new_value().map(String::from);
+                Change::new(c.key().to_string(), new, old)
+            })
+            .collect();
+        self.replay(Kind::Undo, reverted);
+        true
+    }
+
+    /// Applies again the last operation reverted by `undo`, returns false if
+    /// there is nothing to redo. Any new change drops the operations which
+    /// could be redone.
+    pub fn redo(&mut self) -> bool {
+        let changes = match self.journal.as_mut() {
+            Some(j) if j.position < j.entries.len() => {
+                j.position += 1;
+                j.entries[j.position - 1].clone()
+            }
+            _ => return false,
+        };
+        self.replay(Kind::Redo, changes);
+        true
+    }
+
+    /// Names the current state, so that `restore_checkpoint` could return to
+    /// it later. An existing checkpoint of the same name is replaced.
+    pub fn checkpoint(&mut self, name: &str) -> Result<()> {
+        match self.journal.as_mut() {
+            Some(j) => {
+                j.checkpoints.insert(name.to_string(), j.position);
+                Ok(())
+            }
+            None => Err(PropertiesError::new("journal is disabled")),
+        }
+    }
+
+    /// Undoes or redoes operations until the state of checkpoint `name` is
+    /// reached. Fails if the checkpoint is unknown, or was dropped because
+    /// the operations after it were undone and replaced by new changes.
+    pub fn restore_checkpoint(&mut self, name: &str) -> Result<()> {
+        let target = match self.journal.as_ref() {
+            Some(j) => j.checkpoints.get(name).copied(),
+            None => return Err(PropertiesError::new("journal is disabled")),
+        };
+        let target =
+            target.ok_or_else(|| PropertiesError::new(format!("unknown checkpoint '{}'", name)))?;
+        loop {
+            let position = self.journal.as_ref().map_or(target, |j| j.position);
+            if position > target {
+                self.undo();
+            } else if position < target {
+                self.redo();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes the journal as an audit trail, every change applied since it
+    /// was enabled in order, including the ones made by `undo` and `redo`.
+    /// Each line starts with the number of the operation and its kind, e.g.
+    /// `2 edit set db.host=db2 (was db1)` or `3 undo remove db.pool (was 4)`,
+    /// keys and values are escaped as in `store`, redacted values are masked.
+    pub fn export_journal<W: Write>(&mut self, mut writer: W) -> Result<()> {
+        let trail = match self.journal.as_ref() {
+            Some(j) => &j.trail,
+            None => return Ok(()),
+        };
+        for (i, (kind, changes)) in trail.iter().enumerate() {
+            for change in changes {
+                let key = save_convert(&change.key().to_string(), true, false)?;
+                write!(writer, "{} {} ", i + 1, kind.name())?;
+                match change.new_value() {
+                    Some(v) => {
+                        writer.write_all(b"set ")?;
+                        writer.write_all(&key)?;
+                        writer.write_all(b"=")?;
+                        let v = self.masked(change.key(), v);
+                        writer.write_all(&save_convert(&v.to_string(), false, false)?)?;
+                    }
+                    None => {
+                        writer.write_all(b"remove ")?;
+                        writer.write_all(&key)?;
+                    }
+                }
+                if let Some(v) = change.old_value() {
+                    let v = self.masked(change.key(), v);
+                    writer.write_all(b" (was ")?;
+                    writer.write_all(&save_convert(&v.to_string(), false, false)?)?;
+                    writer.write_all(b")")?;
+                }
+                writer.write_all(b"\n")?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Properties;
+
+    #[test]
+    fn undo_redo() {
+        let mut prop = Properties::new();
+        prop.set("ignored", "1");
+        prop.journal(true);
+        prop.load("db.host=db1\ndb.port=5432\n".as_bytes()).unwrap();
+        prop.set("db.host", "db2");
+        prop.remove("db.port");
+
+        assert!(prop.undo());
+        assert_eq!(prop.get("db.port").unwrap(), "5432");
+        assert!(prop.undo());
+        assert_eq!(prop.get("db.host").unwrap(), "db1");
+        assert!(prop.undo());
+        assert_eq!(prop.len(), 1);
+        assert!(!prop.undo());
+
+        assert!(prop.redo());
+        assert!(prop.redo());
+        assert_eq!(prop.get("db.host").unwrap(), "db2");
+        assert_eq!(prop.get("db.port").unwrap(), "5432");
+
+        // a new change drops what could be redone
+        prop.set("db.user", "admin");
+        assert!(!prop.redo());
+        assert!(prop.undo());
+        assert_eq!(prop.get("db.user"), None);
+    }
+
+    #[test]
+    fn checkpoints() {
+        let mut prop = Properties::new();
+        if prop.checkpoint("start").is_ok() {
+            panic!("checkpoint should failed without journal");
+        }
+        prop.journal(true);
+        prop.set("a", "1");
+        prop.checkpoint("one").unwrap();
+        prop.set("a", "2");
+        prop.set("b", "2");
+        prop.checkpoint("two").unwrap();
+        prop.set("a", "3");
+
+        prop.restore_checkpoint("one").unwrap();
+        assert_eq!(prop.get("a").unwrap(), "1");
+        assert_eq!(prop.get("b"), None);
+        prop.restore_checkpoint("two").unwrap();
+        assert_eq!(prop.get("a").unwrap(), "2");
+        assert_eq!(prop.get("b").unwrap(), "2");
+
+        prop.restore_checkpoint("one").unwrap();
+        prop.set("c", "1");
+        match prop.restore_checkpoint("two") {
+            Ok(_) => panic!("checkpoint should be dropped"),
+            Err(e) => assert_eq!(format!("{}", e), "unknown checkpoint 'two'"),
+        }
+    }
+
+    #[test]
+    fn export() {
+        let mut prop = Properties::new();
+        prop.journal(true);
+        prop.load("a=1\nb=x y\n".as_bytes()).unwrap();
+        prop.set("a", "2");
+        prop.remove("b");
+        prop.undo();
+
+        let mut out = Vec::new();
+        prop.export_journal(&mut out).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "1 edit set a=1",
+                "1 edit set b=x y",
+                "2 edit set a=2 (was 1)",
+                "3 edit remove b (was x y)",
+                "4 undo set b=x y",
+            ]
+        );
+
+        prop.redact_key("b");
+        let mut out = Vec::new();
+        prop.export_journal(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("3 edit remove b (was ******)\n"), "{}", text);
+        assert!(!text.contains("x y"), "{}", text);
+    }
+}
@@ -0,0 +1,275 @@
+use super::{Properties, PropertiesError, Result};
+
+fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\')
+    {
+        return arg.to_string();
+    }
+    let mut result = String::with_capacity(arg.len() + 2);
+    result.push('"');
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result.push('"');
+    result
+}
+
+/// Splits a single string of JVM options, e.g. the value of
+/// `JDK_JAVA_OPTIONS`, into arguments. Arguments are separated by
+/// whitespace, which could be kept in single or double quotes, and a
+/// backslash escapes the next character outside single quotes.
+pub fn split_jvm_options(line: &str) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    let mut arg = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => arg.push(c),
+            (_, '\\') => match chars.next() {
+                Some(n) => {
+                    arg.push(n);
+                    in_arg = true;
+                }
+                None => return Err(PropertiesError::new("invalid options, trailing backslash")),
+            },
+            (Some(_), _) => arg.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, _) if c.is_whitespace() => {
+                if in_arg {
+                    result.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            (None, _) => {
+                arg.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if let Some(q) = quote {
+        return Err(PropertiesError::new(format!(
+            "invalid options, unterminated quote {}",
+            q
+        )));
+    }
+    if in_arg {
+        result.push(arg);
+    }
+    Ok(result)
+}
+
+impl Properties {
+    /// Sets the system properties of all `-Dkey=value` arguments, like the
+    /// `java` launcher: `-Dkey` sets an empty value and the first '=' splits
+    /// the key and the value. The arguments are taken as the launcher sees
+    /// them, i.e. after any shell quoting was removed, so quotes are part of
+    /// the value. Use `split_jvm_options` for a single string of options.
+    ///
+    /// Returns the other arguments in order, nothing is set if any `-D`
+    /// argument is invalid.
+    pub fn overlay_jvm_args<I, S>(&mut self, args: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut defines = Vec::new();
+        let mut others = Vec::new();
+        for arg in args {
+            let arg = arg.as_ref();
+            let def = match arg.strip_prefix("-D") {
+                Some(def) => def,
+                None => {
+                    others.push(arg.to_string());
+                    continue;
+                }
+            };
+            let (key, val) = match def.split_once('=') {
+                Some((k, v)) => (k, v),
+                None => (def, ""),
+            };
+            if key.is_empty() {
+                return Err(PropertiesError::new(format!(
+                    "invalid argument '{}', empty key",
+                    arg
+                )));
+            }
+            defines.push((key.to_string(), val.to_string()));
+        }
+
+        self.batch(|p| {
+            for (k, v) in defines {
+                p.set(&k, &v);
+            }
+        });
+        Ok(others)
+    }
+
+    /// Renders all the properties as `-Dkey=value` arguments sorted by key,
+    /// ready for `std::process::Command::args` without any shell quoting.
+    ///
+    /// Fails if a key is empty or contains '=', or anything contains NUL,
+    /// which could not be passed back unchanged.
+    pub fn to_jvm_args(&mut self) -> Result<Vec<String>> {
+        let data = self.data.lock().unwrap();
+        let mut keys: Vec<&String> = data.keys().collect();
+        keys.sort();
+
+        let mut result = Vec::with_capacity(keys.len());
+        for k in keys {
+            let v = &data[k];
+            if k.is_empty() || k.contains('=') {
+                return Err(PropertiesError::new(format!(
+                    "invalid system property key '{}'",
+                    k
+                )));
+            }
+            if k.contains('\0') || v.contains('\0') {
+                return Err(PropertiesError::new(format!(
+                    "invalid system property '{}', contains NUL",
+                    k
+                )));
+            }
+            result.push(format!("-D{}={}", k, v));
+        }
+        Ok(result)
+    }
+
+    /// Same as `to_jvm_args`, joined into a single string quoted for
+    /// `split_jvm_options`, e.g. for `JDK_JAVA_OPTIONS`.
+    pub fn to_jvm_options(&mut self) -> Result<String> {
+        let args = self.to_jvm_args()?;
+        let quoted: Vec<String> = args.iter().map(|a| quote(a)).collect();
+        Ok(quoted.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_jvm_options, Properties};
+
+    #[test]
+    fn overlay() {
+        let args = vec![
+            "-Xmx1g",
+            "-Dfile.encoding=UTF-8",
+            "-Durl=jdbc:h2:mem;MODE=MySQL",
+            "-Dflag",
+            "-Dempty=",
+            "-Dname=John Doe",
+            "-Dquoted='x y'",
+            "-jar",
+            "app.jar",
+        ];
+        let mut prop = Properties::new();
+        let others = prop.overlay_jvm_args(&args).unwrap();
+        assert_eq!(others, vec!["-Xmx1g", "-jar", "app.jar"]);
+
+        let cases = vec![
+            ("file.encoding", "UTF-8"),
+            ("url", "jdbc:h2:mem;MODE=MySQL"),
+            ("flag", ""),
+            ("empty", ""),
+            ("name", "John Doe"),
+            ("quoted", "'x y'"),
+        ];
+        assert_eq!(prop.len(), cases.len());
+        for &(key, val) in &cases {
+            assert_eq!(prop.get(key).unwrap(), val, "{}", key);
+        }
+
+        for arg in &["-D", "-D=value"] {
+            let mut prop = Properties::new();
+            match prop.overlay_jvm_args(["-Da=b", *arg]) {
+                Ok(_) => panic!("argument {} should be invalid", arg),
+                Err(e) => assert!(format!("{}", e).starts_with("invalid argument")),
+            }
+            assert_eq!(prop.len(), 0);
+        }
+    }
+
+    #[test]
+    fn split() {
+        let cases = vec![
+            ("", vec![]),
+            ("  -Xmx1g   -Da=b ", vec!["-Xmx1g", "-Da=b"]),
+            ("-Dname=\"John Doe\"", vec!["-Dname=John Doe"]),
+            ("'-Dname=a \"b\"'", vec!["-Dname=a \"b\""]),
+            (
+                "-Dpath=C:\\\\tmp -Da=\\\"",
+                vec!["-Dpath=C:\\tmp", "-Da=\""],
+            ),
+            ("\"\" x", vec!["", "x"]),
+        ];
+        for (line, expected) in &cases {
+            assert_eq!(&split_jvm_options(line).unwrap(), expected, "{}", line);
+        }
+        for line in &["-Da=\"b", "-Da='b", "-Da=b\\"] {
+            if split_jvm_options(line).is_ok() {
+                panic!("options {} should be invalid", line);
+            }
+        }
+    }
+
+    #[test]
+    fn render() {
+        let mut prop = Properties::new();
+        prop.set("url", "jdbc:h2:mem;MODE=MySQL");
+        prop.set("name", "John \"JD\" Doe");
+        prop.set("flag", "");
+        assert_eq!(
+            prop.to_jvm_args().unwrap(),
+            vec![
+                "-Dflag=",
+                "-Dname=John \"JD\" Doe",
+                "-Durl=jdbc:h2:mem;MODE=MySQL"
+            ]
+        );
+
+        // round trip through a single options string
+        let options = prop.to_jvm_options().unwrap();
+        assert_eq!(
+            options,
+            "-Dflag= \"-Dname=John \\\"JD\\\" Doe\" -Durl=jdbc:h2:mem;MODE=MySQL"
+        );
+        let mut parsed = Properties::new();
+        parsed
+            .overlay_jvm_args(split_jvm_options(&options).unwrap())
+            .unwrap();
+        assert_eq!(parsed.get("name").unwrap(), "John \"JD\" Doe");
+
+        // quotes around a value are part of it
+        let mut prop = Properties::new();
+        prop.set("single", "'quoted'");
+        prop.set("double", "\"quoted\"");
+        let args = prop.to_jvm_args().unwrap();
+        assert_eq!(args, vec!["-Ddouble=\"quoted\"", "-Dsingle='quoted'"]);
+        let options = prop.to_jvm_options().unwrap();
+        for args in [args, split_jvm_options(&options).unwrap()] {
+            let mut parsed = Properties::new();
+            parsed.overlay_jvm_args(&args).unwrap();
+            assert_eq!(parsed.get("single").unwrap(), "'quoted'");
+            assert_eq!(parsed.get("double").unwrap(), "\"quoted\"");
+        }
+
+        for key in &["", "a=b"] {
+            let mut prop = Properties::new();
+            prop.set(key, "v");
+            if prop.to_jvm_args().is_ok() {
+                panic!("key '{}' should be invalid", key);
+            }
+        }
+    }
+}
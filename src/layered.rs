@@ -0,0 +1,358 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+use super::{Change, EnvNaming, Origin, Properties, PropertiesError, ReadOption, Result};
+
+/// A source of properties, e.g. a file, the environment or the command line,
+/// which could be stacked into a `LayeredProperties`.
+pub trait PropertySource {
+    /// Returns a readable name of the source, used in error messages.
+    fn name(&self) -> String;
+
+    /// Loads all the properties of the source into `prop`.
+    fn load_into(&self, prop: &mut Properties) -> Result<()>;
+}
+
+/// Properties file on disk, read with `Properties::load`.
+pub struct FileSource {
+    path: PathBuf,
+    optional: bool,
+}
+
+impl FileSource {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            optional: false,
+        }
+    }
+
+    /// A missing file is silently skipped instead of being an error.
+    pub fn optional<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            optional: true,
+        }
+    }
+}
+
+impl PropertySource for FileSource {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load_into(&self, prop: &mut Properties) -> Result<()> {
+        if self.optional && !self.path.exists() {
+            return Ok(());
+        }
+        let mut opt = ReadOption::default();
+        opt.origins(true);
+        prop.load_file(&self.path, &opt)
+    }
+}
+
+/// Properties text held in memory, e.g. compiled in defaults.
+pub struct MemorySource {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl MemorySource {
+    pub fn new<S: Into<String>, D: Into<Vec<u8>>>(name: S, data: D) -> Self {
+        Self {
+            name: name.into(),
+            data: data.into(),
+        }
+    }
+}
+
+impl PropertySource for MemorySource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn load_into(&self, prop: &mut Properties) -> Result<()> {
+        let mut opt = ReadOption::default();
+        opt.source(self.name());
+        opt.origins(true);
+        prop.load_with(self.data.as_slice(), &opt)
+    }
+}
+
+/// Environment variables mapped to keys by an `EnvNaming`, e.g. with prefix
+/// `APP` the variable `APP_DB_URL` is mapped to `db.url`.
+pub struct EnvSource {
+    naming: EnvNaming,
+}
+
+impl EnvSource {
+    pub fn new<S: Into<String>>(prefix: S) -> Self {
+        Self::with_naming(EnvNaming::new(prefix))
+    }
+
+    pub fn with_naming(naming: EnvNaming) -> Self {
+        Self { naming }
+    }
+}
+
+impl PropertySource for EnvSource {
+    fn name(&self) -> String {
+        format!("environment {}", self.naming.to_var("*"))
+    }
+
+    fn load_into(&self, prop: &mut Properties) -> Result<()> {
+        prop.overlay_env_with(&self.naming);
+        Ok(())
+    }
+}
+
+/// Command line arguments in the `--key=value` form, all the other arguments
+/// are ignored.
+pub struct ArgsSource {
+    args: Vec<String>,
+}
+
+impl ArgsSource {
+    pub fn new<I, S>(args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl PropertySource for ArgsSource {
+    fn name(&self) -> String {
+        "command line".to_string()
+    }
+
+    fn load_into(&self, prop: &mut Properties) -> Result<()> {
+        for arg in &self.args {
+            if let Some((k, v)) = arg.strip_prefix("--").and_then(|a| a.split_once('=')) {
+                if k.is_empty() {
+                    return Err(PropertiesError::new(format!(
+                        "invalid argument '{}', empty key",
+                        arg
+                    )));
+                }
+                prop.set(k, v);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Properties merged from a stack of sources, where the later layers
+/// override the earlier ones. The effective values are read through the
+/// normal `Properties` methods.
+pub struct LayeredProperties {
+    names: Vec<String>,
+    // the layer and the origin of the values provided by a layer
+    provided: HashMap<String, (usize, Origin)>,
+    finals: HashSet<String>,
+    merged: Properties,
+}
+
+impl LayeredProperties {
+    pub fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            provided: HashMap::new(),
+            finals: HashSet::new(),
+            merged: Properties::new(),
+        }
+    }
+
+    /// Loads `source` as a new top layer. Nothing is changed if the source
+    /// fails to load.
+    pub fn add<S: PropertySource + ?Sized>(&mut self, source: &S) -> Result<()> {
+        let mut layer = Properties::new();
+        if let Err(e) = source.load_into(&mut layer) {
+            return Err(PropertiesError::with_cause(
+                format!("load source '{}' failed", source.name()),
+                Some(Box::new(e)),
+            ));
+        }
+        self.push(source.name(), layer);
+        Ok(())
+    }
+
+    pub(crate) fn push(&mut self, name: String, layer: Properties) {
+        let index = self.names.len();
+        let data = layer.data.lock().unwrap();
+        let mut merged = self.merged.data.lock().unwrap();
+        let mut changes = Vec::new();
+        for (k, v) in data.iter() {
+            if self.finals.contains(k) && merged.contains_key(k) {
+                continue;
+            }
+            let origin = match layer.origins.get(k) {
+                Some(origin) => origin.clone(),
+                None => Origin::named(name.clone()),
+            };
+            let old = merged.insert(k.clone(), v.clone());
+            changes.push(Change::new(k.clone(), old, Some(v.clone())));
+            self.merged.origins.insert(k.clone(), origin.clone());
+            self.provided.insert(k.clone(), (index, origin));
+        }
+        drop(merged);
+        self.merged.batch(|p| {
+            for change in changes {
+                p.record_change(change);
+            }
+        });
+        self.names.push(name);
+    }
+
+    /// Marks `key` as final, its current effective value can no longer be
+    /// overridden by the layers added afterwards. A key without a value yet
+    /// becomes final once a layer provides it.
+    ///
+    /// Only layers are restricted, the key could still be changed directly,
+    /// e.g. with `set`.
+    pub fn mark_final(&mut self, key: &str) {
+        self.finals.insert(key.to_string());
+    }
+
+    /// Returns the names of all the layers, from the lowest to the highest
+    /// precedence.
+    pub fn layers(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the name of the layer which provided the value of `key`, None
+    /// if the key is missing or its value was changed directly since.
+    pub fn layer_of(&mut self, key: &str) -> Option<&str> {
+        let (index, origin) = self.provided.get(key)?;
+        // set, remove and load replace the origin of the layer
+        if self.merged.origins.get(key) != Some(origin) {
+            return None;
+        }
+        Some(self.names[*index].as_str())
+    }
+}
+
+impl Default for LayeredProperties {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for LayeredProperties {
+    type Target = Properties;
+
+    fn deref(&self) -> &Properties {
+        &self.merged
+    }
+}
+
+impl DerefMut for LayeredProperties {
+    fn deref_mut(&mut self) -> &mut Properties {
+        &mut self.merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArgsSource, EnvSource, FileSource, LayeredProperties, MemorySource};
+
+    #[test]
+    fn precedence() {
+        let mut prop = LayeredProperties::new();
+        let defaults = MemorySource::new("defaults", "db.url=jdbc:h2:mem\ndb.user=sa\nport=80\n");
+        let env = MemorySource::new("production", "db.url=jdbc:pg://db\nport=8080\n");
+        let args = ArgsSource::new(vec!["serve", "--port=9090", "--debug=true"]);
+
+        prop.add(&defaults).unwrap();
+        prop.add(&env).unwrap();
+        prop.add(&args).unwrap();
+        assert_eq!(prop.layers(), &["defaults", "production", "command line"]);
+
+        let cases = vec![
+            ("db.url", "jdbc:pg://db", "production"),
+            ("db.user", "sa", "defaults"),
+            ("port", "9090", "command line"),
+            ("debug", "true", "command line"),
+        ];
+        for &(key, val, layer) in &cases {
+            assert_eq!(prop.get(key).unwrap(), val);
+            assert_eq!(prop.layer_of(key).unwrap(), layer);
+        }
+        assert_eq!(prop.len(), 4);
+
+        prop.set("db.user", "admin");
+        assert_eq!(prop.get("db.user").unwrap(), "admin");
+        assert_eq!(prop.layer_of("db.user"), None);
+        assert_eq!(prop.origin("db.user"), None);
+        prop.remove("debug");
+        assert_eq!(prop.layer_of("debug"), None);
+
+        assert_eq!(
+            format!("{}", prop.origin("db.url").unwrap()),
+            "production:1:1-1:19"
+        );
+        assert_eq!(format!("{}", prop.origin("port").unwrap()), "command line");
+    }
+
+    #[test]
+    fn finals() {
+        let mut prop = LayeredProperties::new();
+        prop.add(&MemorySource::new("defaults", "a=1\nb=1\nc=1\n"))
+            .unwrap();
+        prop.mark_final("a");
+        prop.mark_final("c");
+        prop.add(&MemorySource::new("override", "a=2\nb=2\n"))
+            .unwrap();
+        prop.mark_final("b");
+        prop.add(&MemorySource::new("override", "a=3\nb=3\nc=3\n"))
+            .unwrap();
+
+        assert_eq!(prop.get("a").unwrap(), "1");
+        assert_eq!(prop.get("b").unwrap(), "2");
+        assert_eq!(prop.get("c").unwrap(), "1");
+
+        // final before any layer provides it
+        prop.mark_final("x");
+        prop.add(&MemorySource::new("late", "x=1\n")).unwrap();
+        prop.add(&MemorySource::new("later", "x=2\n")).unwrap();
+        assert_eq!(prop.get("x").unwrap(), "1");
+        assert_eq!(prop.layer_of("x").unwrap(), "late");
+
+        // direct changes are not restricted
+        prop.set("a", "4");
+        assert_eq!(prop.get("a").unwrap(), "4");
+    }
+
+    #[test]
+    fn sources() {
+        let mut prop = LayeredProperties::new();
+        prop.add(&FileSource::optional("/nonexistent/app.properties"))
+            .unwrap();
+        assert_eq!(prop.len(), 0);
+
+        match prop.add(&FileSource::new("/nonexistent/app.properties")) {
+            Ok(_) => panic!("missing file should failed"),
+            Err(e) => {
+                let msg = format!("{}", e);
+                assert!(
+                    msg.starts_with("load source '/nonexistent/app.properties' failed"),
+                    "{}",
+                    msg
+                );
+            }
+        }
+        assert_eq!(prop.layers().len(), 1);
+
+        std::env::set_var("PROPS_LAYERED_TEST_DB_URL", "jdbc:h2:env");
+        prop.add(&EnvSource::new("PROPS_LAYERED_TEST")).unwrap();
+        assert_eq!(prop.get("db.url").unwrap(), "jdbc:h2:env");
+
+        if prop.add(&ArgsSource::new(vec!["--=value"])).is_ok() {
+            panic!("empty key should failed");
+        }
+    }
+}
@@ -1,16 +1,67 @@
+mod access;
+mod alias;
+mod codec;
+mod diff;
+mod dir;
+mod document;
+mod env;
+mod file;
+mod global;
+mod include;
+mod journal;
+mod jvm;
+mod layered;
+mod listener;
+mod lock;
+mod merge;
+mod migrate;
+mod origin;
+mod profile;
+mod query;
 mod reader;
+mod redact;
+mod relaxed;
+mod reload;
+mod require;
+#[cfg(test)]
+mod testutil;
+mod transaction;
+mod tree;
 mod writer;
 
+pub use alias::AliasPolicy;
+pub use codec::{Base64Codec, ValueCodec};
+pub use diff::Diff;
+pub use document::Document;
+pub use env::EnvNaming;
+pub use file::PropertiesFile;
+pub use global::{global, Global, ScopedOverride};
+pub use jvm::split_jvm_options;
+pub use layered::{
+    ArgsSource, EnvSource, FileSource, LayeredProperties, MemorySource, PropertySource,
+};
+pub use listener::{Change, Subscription};
+pub use lock::{try_update_locked, update_locked, LockOption};
+pub use merge::{merge3, MergeConflict, Merged};
+pub use migrate::{Migration, MigrationReport, Rule};
+pub use origin::Origin;
+pub use profile::load_profiles;
+pub use reader::ReadOption;
+pub use reload::{ReloadingProperties, Watcher};
+pub use transaction::Transaction;
 pub use writer::{WriteOption, CR, CRLF, LF};
 
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+#[derive(Debug)]
 pub struct PropertiesError {
     desc: String,
     cause: Option<Box<dyn Error>>,
+    path: Option<PathBuf>,
 }
 
 impl PropertiesError {
@@ -18,6 +69,7 @@ impl PropertiesError {
         Self {
             desc: desc.into(),
             cause: None,
+            path: None,
         }
     }
 
@@ -25,12 +77,29 @@ impl PropertiesError {
         Self {
             desc: desc.into(),
             cause,
+            path: None,
         }
     }
+
+    // Attach the file being processed, the innermost one is kept.
+    fn with_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path.into());
+        }
+        self
+    }
+
+    /// Returns the file which failed to load, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 }
 
 impl Display for PropertiesError {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(fmt, "{}: ", path.display())?;
+        }
         match &self.cause {
             None => write!(fmt, "{}", self.desc),
             Some(e) => write!(fmt, "{}, {:?}", self.desc, e),
@@ -38,6 +107,12 @@ impl Display for PropertiesError {
     }
 }
 
+impl Error for PropertiesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.cause.as_deref()
+    }
+}
+
 impl From<std::io::Error> for PropertiesError {
     fn from(e: std::io::Error) -> Self {
         PropertiesError::with_cause("io error", Some(Box::new(e)))
@@ -54,12 +129,34 @@ type Result<T> = std::result::Result<T, PropertiesError>;
 
 pub struct Properties {
     data: Arc<Mutex<HashMap<String, String>>>,
+    relaxed: bool,
+    access: Option<access::Access>,
+    origins: HashMap<String, Origin>,
+    listeners: Arc<Mutex<listener::Listeners>>,
+    batch: Option<listener::Batch>,
+    journal: Option<journal::Journal>,
+    aliases: alias::Aliases,
+    redaction: redact::Redaction,
+    codecs: codec::Codecs,
 }
 
 impl Properties {
     pub fn new() -> Self {
+        Self::from_map(HashMap::new())
+    }
+
+    pub(crate) fn from_map(data: HashMap<String, String>) -> Self {
         Self {
-            data: Arc::new(Mutex::new(HashMap::new())),
+            data: Arc::new(Mutex::new(data)),
+            relaxed: false,
+            access: None,
+            origins: HashMap::new(),
+            listeners: Arc::new(Mutex::new(listener::Listeners::default())),
+            batch: None,
+            journal: None,
+            aliases: alias::Aliases::default(),
+            redaction: redact::Redaction::default(),
+            codecs: codec::Codecs::default(),
         }
     }
 
@@ -69,14 +166,53 @@ impl Properties {
 
     pub fn set(&mut self, key: &str, value: &str) {
         let mut data = self.data.lock().unwrap();
-        data.insert(key.to_string(), value.to_string());
+        let key = self
+            .resolve_key(&data, key)
+            .unwrap_or_else(|| key.to_string());
+        self.origins.remove(&key);
+        let old = data.insert(key.clone(), value.to_string());
+        drop(data);
+        self.record_change(Change::new(key, old, Some(value.to_string())));
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let mut data = self.data.lock().unwrap();
+        let key = self.resolve_key(&data, key)?;
+        self.origins.remove(&key);
+        let old = data.remove(&key);
+        drop(data);
+        self.record_change(Change::new(key, old.clone(), None));
+        old
+    }
+
+    // The stored value of key as is, without aliases, codecs or access
+    // tracking, e.g. to be restored later.
+    pub(crate) fn get_raw(&self, key: &str) -> Option<String> {
+        let data = self.data.lock().unwrap();
+        let key = self.resolve_key(&data, key)?;
+        data.get(&key).cloned()
     }
 
+    /// Returns the value of `key`, decoded by the matching codec if any. A
+    /// value failing to decode is treated as missing, see `try_get`.
     pub fn get(&mut self, key: &str) -> Option<String> {
+        self.try_get(key).unwrap_or(None)
+    }
+
+    /// Same as `get`, fails if the value could not be decoded.
+    pub fn try_get(&mut self, key: &str) -> Result<Option<String>> {
         let data = self.data.lock().unwrap();
-        match data.get(key) {
-            Some(val) => Some(val.clone()),
-            None => None,
+        let stored = self.resolve_key(&data, key);
+        let (stored, deprecated) = self.resolve_alias(&data, key, stored);
+        let found = stored.and_then(|k| data.get(&k).map(|v| (k, v.clone())));
+        drop(data);
+        if let Some(deprecated) = deprecated {
+            self.warn_deprecated(&deprecated, key);
+        }
+        self.record_access(key, found.as_ref().map(|(k, _)| k.as_str()));
+        match found {
+            Some((k, v)) => self.decode_value(&k, v).map(Some),
+            None => Ok(None),
         }
     }
 }
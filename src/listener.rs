@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::tree::{normalize_prefix, strip_segment_prefix};
+use super::Properties;
+
+type Callback = Arc<dyn Fn(&[Change]) + Send + Sync>;
+
+/// The change of a single key, a missing value is `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    key: String,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl Change {
+    pub(crate) fn new(key: String, old: Option<String>, new: Option<String>) -> Self {
+        Self { key, old, new }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn old_value(&self) -> Option<&str> {
+        self.old.as_deref()
+    }
+
+    pub fn new_value(&self) -> Option<&str> {
+        self.new.as_deref()
+    }
+}
+
+pub(crate) enum Filter {
+    Key(String),
+    Prefix(String),
+}
+
+impl Filter {
+    fn matches(&self, key: &str) -> bool {
+        match self {
+            Filter::Key(k) => k == key,
+            Filter::Prefix(p) => {
+                key == normalize_prefix(p) || strip_segment_prefix(key, p).is_some()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Listeners {
+    next_id: usize,
+    entries: Vec<(usize, Filter, Callback)>,
+}
+
+/// Returned by `Properties::subscribe` and `Global::subscribe`, the listener
+/// is removed when it's dropped.
+pub struct Subscription {
+    listeners: Weak<Mutex<Listeners>>,
+    id: usize,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(listeners) = self.listeners.upgrade() {
+            listeners
+                .lock()
+                .unwrap()
+                .entries
+                .retain(|(id, _, _)| *id != self.id);
+        }
+    }
+}
+
+// Changes collected while an operation is running, a key changed several
+// times is reported once with its first old and last new value.
+#[derive(Default)]
+pub(crate) struct Batch {
+    changes: Vec<Change>,
+    index: HashMap<String, usize>,
+}
+
+impl Batch {
+    fn record(&mut self, change: Change) {
+        match self.index.get(&change.key) {
+            Some(&i) => self.changes[i].new = change.new,
+            None => {
+                self.index.insert(change.key.clone(), self.changes.len());
+                self.changes.push(change);
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<Change> {
+        let mut changes = self.changes;
+        changes.retain(|c| c.old != c.new);
+        changes
+    }
+}
+
+pub(crate) fn register(
+    listeners: &Arc<Mutex<Listeners>>,
+    filter: Filter,
+    f: Callback,
+) -> Subscription {
+    let mut entries = listeners.lock().unwrap();
+    let id = entries.next_id;
+    entries.next_id += 1;
+    entries.entries.push((id, filter, f));
+    Subscription {
+        listeners: Arc::downgrade(listeners),
+        id,
+    }
+}
+
+// Call every listener with the changes matching its filter, the listeners
+// run without the lock held, so they could subscribe or unsubscribe.
+pub(crate) fn notify(listeners: &Mutex<Listeners>, changes: &[Change]) {
+    if changes.is_empty() {
+        return;
+    }
+    let matched: Vec<(Callback, Vec<Change>)> = listeners
+        .lock()
+        .unwrap()
+        .entries
+        .iter()
+        .filter_map(|(_, filter, f)| {
+            let matched: Vec<Change> = changes
+                .iter()
+                .filter(|c| filter.matches(&c.key))
+                .cloned()
+                .collect();
+            if matched.is_empty() {
+                None
+            } else {
+                Some((f.clone(), matched))
+            }
+        })
+        .collect();
+    for (f, changes) in matched {
+        f(&changes);
+    }
+}
+
+impl Properties {
+    /// Registers `f` to be called after every operation changing `key`, e.g.
+    /// `set`, `remove`, `load` or a merge, until the returned `Subscription`
+    /// is dropped. The key is matched exactly against the stored keys.
+    pub fn subscribe<F>(&mut self, key: &str, f: F) -> Subscription
+    where
+        F: Fn(&[Change]) + Send + Sync + 'static,
+    {
+        register(&self.listeners, Filter::Key(key.to_string()), Arc::new(f))
+    }
+
+    /// Same as `subscribe`, for `prefix` and every key under it, an empty
+    /// prefix matches every key.
+    ///
+    /// An operation changing several keys calls `f` once with all the
+    /// matching changes, in the order the keys were first changed.
+    pub fn subscribe_prefix<F>(&mut self, prefix: &str, f: F) -> Subscription
+    where
+        F: Fn(&[Change]) + Send + Sync + 'static,
+    {
+        register(
+            &self.listeners,
+            Filter::Prefix(prefix.to_string()),
+            Arc::new(f),
+        )
+    }
+
+    // Run f as a single operation, all the changes recorded meanwhile are
+    // reported together when it returns, nested calls join the outer batch.
+    // If f panics the changes it made are still reported before the panic
+    // continues, so later changes aren't swallowed by a stale batch.
+    pub(crate) fn batch<R, F: FnOnce(&mut Self) -> R>(&mut self, f: F) -> R {
+        if self.batch.is_some() {
+            return f(self);
+        }
+        self.batch = Some(Batch::default());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        if let Some(batch) = self.batch.take() {
+            self.changed(batch.finish());
+        }
+        result.unwrap_or_else(|e| panic::resume_unwind(e))
+    }
+
+    // Record a change made to the data, must be called without the data
+    // lock held.
+    pub(crate) fn record_change(&mut self, change: Change) {
+        match self.batch.as_mut() {
+            Some(batch) => batch.record(change),
+            None if change.old != change.new => self.changed(vec![change]),
+            None => {}
+        }
+    }
+
+    fn changed(&mut self, changes: Vec<Change>) {
+        self.record_journal(&changes);
+        notify(&self.listeners, &changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    use super::{Change, Properties};
+
+    type Event = (String, Option<String>, Option<String>);
+    type Events = Arc<Mutex<Vec<Vec<Event>>>>;
+
+    fn collect(events: &Events) -> impl Fn(&[Change]) + Send + Sync + 'static {
+        let events = events.clone();
+        move |changes: &[Change]| {
+            let batch = changes
+                .iter()
+                .map(|c| {
+                    let old = c.old_value().map(String::from);
+                    let new = c.new_value().map(String::from);
+                    (c.key().to_string(), old, new)
+                })
+                .collect();
+            events.lock().unwrap().push(batch);
+        }
+    }
+
+    fn change(key: &str, old: Option<&str>, new: Option<&str>) -> Event {
+        (
+            key.to_string(),
+            old.map(String::from),
+            new.map(String::from),
+        )
+    }
+
+    #[test]
+    fn subscribe() {
+        let mut prop = Properties::new();
+        let key_events = Events::default();
+        let prefix_events = Events::default();
+        let key_sub = prop.subscribe("log.level", collect(&key_events));
+        let _prefix_sub = prop.subscribe_prefix("pool", collect(&prefix_events));
+
+        prop.set("log.level", "info");
+        prop.set("log.level", "info");
+        prop.set("pool.size", "4");
+        prop.set("poolx.size", "4");
+        prop.load("log.level=debug\npool.size=8\npool.size=4\npool=x\n".as_bytes())
+            .unwrap();
+        prop.remove("pool.size");
+        prop.remove("pool.size");
+        drop(key_sub);
+        prop.set("log.level", "warn");
+
+        assert_eq!(
+            *key_events.lock().unwrap(),
+            vec![
+                vec![change("log.level", None, Some("info"))],
+                vec![change("log.level", Some("info"), Some("debug"))],
+            ]
+        );
+        // the load changed pool.size back to its old value, so only pool is
+        // reported
+        assert_eq!(
+            *prefix_events.lock().unwrap(),
+            vec![
+                vec![change("pool.size", None, Some("4"))],
+                vec![change("pool", None, Some("x"))],
+                vec![change("pool.size", Some("4"), None)],
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_panics() {
+        let mut prop = Properties::new();
+        let events = Events::default();
+        let _sub = prop.subscribe_prefix("", collect(&events));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            prop.batch(|p| {
+                p.set("a", "1");
+                panic!("in batch");
+            })
+        }));
+        assert!(result.is_err());
+        prop.set("b", "2");
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                vec![change("a", None, Some("1"))],
+                vec![change("b", None, Some("2"))],
+            ]
+        );
+    }
+
+    #[test]
+    fn merges() {
+        let mut prop = Properties::new();
+        let events = Events::default();
+        let _sub = prop.subscribe_prefix("", collect(&events));
+
+        prop.load("a.x=1\na.y=2\nb=3\n".as_bytes()).unwrap();
+        prop.rename_prefix("a", "c").unwrap();
+        prop.remove_prefix("c");
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].len(), 3);
+        assert_eq!(
+            events[1],
+            vec![
+                change("a.x", Some("1"), None),
+                change("a.y", Some("2"), None),
+                change("c.x", None, Some("1")),
+                change("c.y", None, Some("2")),
+            ]
+        );
+        assert_eq!(
+            events[2],
+            vec![
+                change("c.x", Some("1"), None),
+                change("c.y", Some("2"), None)
+            ]
+        );
+    }
+}
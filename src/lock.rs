@@ -0,0 +1,269 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::file::{read_optional, write_atomic};
+use super::{Properties, PropertiesError, Result, WriteOption};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Options of `update_locked` and `try_update_locked`.
+pub struct LockOption {
+    sidecar: bool,
+    timeout: Option<Duration>,
+    write: WriteOption,
+}
+
+impl Default for LockOption {
+    fn default() -> Self {
+        Self {
+            sidecar: true,
+            timeout: None,
+            write: WriteOption::default(),
+        }
+    }
+}
+
+impl LockOption {
+    /// Locks `<path>.lock` instead of the file itself, which is the default
+    /// and allows the new content to be written to a temporary file and
+    /// renamed over the old one atomically.
+    ///
+    /// With `false` the file itself is locked and rewritten in place, since
+    /// a rename would replace the locked file. That is NOT atomic, a crash or
+    /// a reader not taking the lock could see an empty or partial file, use
+    /// it only when the file could not be replaced. All the processes must
+    /// agree on the mode.
+    pub fn sidecar(&mut self, val: bool) -> &Self {
+        self.sidecar = val;
+        self
+    }
+
+    /// Gives up waiting for the lock after `val`, by default it waits
+    /// forever.
+    pub fn timeout(&mut self, val: Duration) -> &Self {
+        self.timeout = Some(val);
+        self
+    }
+
+    pub fn write_option(&mut self, val: WriteOption) -> &Self {
+        self.write = val;
+        self
+    }
+}
+
+fn lock_path(path: &Path, opt: &LockOption) -> PathBuf {
+    if !opt.sidecar {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+// Take the exclusive lock, returns false if it's held by someone else and
+// blocking is not allowed or the timeout expired.
+fn acquire(file: &File, opt: &LockOption, block: bool) -> std::io::Result<bool> {
+    let deadline = match (block, opt.timeout) {
+        (false, _) => None,
+        (true, None) => {
+            file.lock()?;
+            return Ok(true);
+        }
+        (true, Some(timeout)) => Some(Instant::now() + timeout),
+    };
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(true),
+            Err(TryLockError::Error(e)) => return Err(e),
+            Err(TryLockError::WouldBlock) => match deadline {
+                Some(deadline) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+                _ => return Ok(false),
+            },
+        }
+    }
+}
+
+fn update<R, F>(path: &Path, opt: &LockOption, block: bool, f: F) -> Result<Option<R>>
+where
+    F: FnOnce(&mut Properties) -> Result<R>,
+{
+    let lock = lock_path(path, opt);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock)
+        .map_err(|e| PropertiesError::from(e).with_path(&lock))?;
+    if !acquire(&file, opt, block).map_err(|e| PropertiesError::from(e).with_path(&lock))? {
+        return match opt.timeout {
+            Some(timeout) if block => Err(PropertiesError::new(format!(
+                "lock is not acquired in {:?}",
+                timeout
+            ))
+            .with_path(&lock)),
+            _ => Ok(None),
+        };
+    }
+
+    let data = if opt.sidecar {
+        read_optional(path)?.unwrap_or_default()
+    } else {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|e| PropertiesError::from(e).with_path(path))?;
+        data
+    };
+    let mut prop = Properties::new();
+    prop.load(data.as_slice()).map_err(|e| e.with_path(path))?;
+
+    let changed = Arc::new(AtomicBool::new(false));
+    let cloned = changed.clone();
+    let subscription = prop.subscribe_prefix("", move |_| cloned.store(true, Ordering::SeqCst));
+    let result = f(&mut prop)?;
+    drop(subscription);
+
+    if changed.load(Ordering::SeqCst) {
+        let mut data = Vec::new();
+        prop.store(&mut data, &opt.write)?;
+        if opt.sidecar {
+            write_atomic(path, &data)?;
+        } else {
+            file.set_len(0)
+                .and_then(|_| file.seek(SeekFrom::Start(0)))
+                .and_then(|_| file.write_all(&data))
+                .and_then(|_| file.sync_all())
+                .map_err(|e| PropertiesError::from(e).with_path(path))?;
+        }
+    }
+    file.unlock()
+        .map_err(|e| PropertiesError::from(e).with_path(&lock))?;
+    Ok(Some(result))
+}
+
+/// Read-modify-write of the file at `path` under an exclusive advisory lock,
+/// so that concurrent updates from several processes never clobber each
+/// other: locks the file (see `LockOption::sidecar`), loads it, calls `f`
+/// and stores the result if anything changed, then unlocks. A missing file
+/// is treated as empty.
+///
+/// Nothing is written if `f` fails. Waits for the lock until the timeout of
+/// `opt` if any.
+pub fn update_locked<P, R, F>(path: P, opt: &LockOption, f: F) -> Result<R>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut Properties) -> Result<R>,
+{
+    let result = update(path.as_ref(), opt, true, f)?;
+    // a blocking update either runs f or fails
+    Ok(result.unwrap())
+}
+
+/// Same as `update_locked`, without waiting, returns None if the lock is
+/// held by someone else.
+pub fn try_update_locked<P, R, F>(path: P, opt: &LockOption, f: F) -> Result<Option<R>>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut Properties) -> Result<R>,
+{
+    update(path.as_ref(), opt, false, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{lock_path, try_update_locked, update_locked, LockOption};
+    use crate::testutil::TempPath;
+
+    fn increment(path: &std::path::Path, opt: &LockOption) {
+        update_locked(path, opt, |p| {
+            let count: usize = p.get("count").map_or(0, |c| c.parse().unwrap());
+            p.set("count", &(count + 1).to_string());
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn concurrent() {
+        for sidecar in [false, true] {
+            let path = TempPath::new(&format!("lock-concurrent-{}", sidecar));
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let path = path.to_path_buf();
+                    thread::spawn(move || {
+                        let mut opt = LockOption::default();
+                        opt.sidecar(sidecar);
+                        for _ in 0..10 {
+                            increment(&path, &opt);
+                        }
+                    })
+                })
+                .collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+            assert_eq!(fs::read_to_string(&path).unwrap(), "count=40\n");
+        }
+    }
+
+    #[test]
+    fn contended() {
+        let path = TempPath::new("lock-contended");
+        // sidecar by default
+        let mut opt = LockOption::default();
+        assert!(lock_path(&path, &opt).to_string_lossy().ends_with(".lock"));
+        let holder = File::create(lock_path(&path, &opt)).unwrap();
+        holder.lock().unwrap();
+
+        let result = try_update_locked(&path, &opt, |p| {
+            p.set("a", "1");
+            Ok(())
+        });
+        assert!(result.unwrap().is_none());
+
+        opt.timeout(Duration::from_millis(30));
+        match update_locked(&path, &opt, |_| Ok(())) {
+            Ok(_) => panic!("lock should be timed out"),
+            Err(e) => assert!(format!("{}", e).contains("lock is not acquired")),
+        }
+        assert!(!path.exists());
+
+        holder.unlock().unwrap();
+        let result = try_update_locked(&path, &opt, |p| {
+            p.set("a", "1");
+            Ok(2)
+        });
+        assert_eq!(result.unwrap(), Some(2));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a=1\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        for sidecar in [false, true] {
+            let path = TempPath::with_content(&format!("lock-permissions-{}", sidecar), "a=1\n");
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+            let mut opt = LockOption::default();
+            opt.sidecar(sidecar);
+            update_locked(&path, &opt, |p| {
+                p.set("a", "2");
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), "a=2\n");
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use super::{Document, Result};
+
+/// A key changed differently on both sides of a three-way merge, a missing
+/// value is `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+    key: String,
+    base: Option<String>,
+    ours: Option<String>,
+    theirs: Option<String>,
+}
+
+impl MergeConflict {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    pub fn ours(&self) -> Option<&str> {
+        self.ours.as_deref()
+    }
+
+    pub fn theirs(&self) -> Option<&str> {
+        self.theirs.as_deref()
+    }
+}
+
+/// The result of `merge3`.
+pub struct Merged {
+    document: Document,
+    conflicts: Vec<MergeConflict>,
+}
+
+impl Merged {
+    /// Returns the merged document, which contains conflict markers if there
+    /// are conflicts.
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    pub fn into_document(self) -> Document {
+        self.document
+    }
+
+    pub fn conflicts(&self) -> &[MergeConflict] {
+        &self.conflicts
+    }
+}
+
+// Render a side of a conflict as an entry line.
+fn side(doc: &Document, key: &str, value: Option<&str>) -> Result<String> {
+    let mut tmp = Document::parse("")?;
+    if let Some(value) = value {
+        tmp.set(key, value)?;
+    }
+    let mut text = format!("{}", tmp);
+    if !text.is_empty() && doc.line_ending() != "\n" {
+        text = text.replace('\n', doc.line_ending());
+    }
+    Ok(text)
+}
+
+/// Merges the changes made from `base` to `theirs` into `ours` key by key,
+/// the way `git merge` does for lines. The layout of `ours` is kept, keys
+/// added by `theirs` are inserted after the key preceding them in `theirs`,
+/// or at the end.
+///
+/// A key changed on both sides to different values is a conflict, the entry
+/// of `ours` is then replaced by the usual `<<<<<<<`, `=======` and
+/// `>>>>>>>` markers around both versions.
+pub fn merge3(base: &Document, ours: &Document, theirs: &Document) -> Result<Merged> {
+    let mut document = ours.clone();
+    let mut conflicts = Vec::new();
+
+    let mut keys: Vec<&str> = ours.keys();
+    for key in theirs.keys().into_iter().chain(base.keys()) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    let theirs_keys = theirs.keys();
+    for key in keys {
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        if o == t || t == b {
+            continue;
+        }
+        if o == b {
+            match t {
+                Some(t) if o.is_some() => document.set(key, t)?,
+                Some(t) => {
+                    // after the nearest preceding key of theirs which exists
+                    let index = theirs_keys.iter().position(|k| *k == key).unwrap();
+                    let after = theirs_keys[..index]
+                        .iter()
+                        .rev()
+                        .find(|k| document.get(k).is_some());
+                    match after {
+                        Some(after) => document.set_after(after, key, t)?,
+                        None => document.set(key, t)?,
+                    }
+                }
+                None => {
+                    document.remove(key);
+                }
+            }
+            continue;
+        }
+
+        let ending = ours.line_ending();
+        let text = format!(
+            "<<<<<<< ours{ending}{}======={ending}{}>>>>>>> theirs{ending}",
+            side(ours, key, o)?,
+            side(ours, key, t)?,
+        );
+        document.insert_raw(o.map(|_| key), &text);
+        document.remove(key);
+        conflicts.push(MergeConflict {
+            key: key.to_string(),
+            base: b.map(String::from),
+            ours: o.map(String::from),
+            theirs: t.map(String::from),
+        });
+    }
+    Ok(Merged {
+        document,
+        conflicts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge3;
+    use crate::Document;
+
+    fn merge(base: &str, ours: &str, theirs: &str) -> (String, Vec<String>) {
+        let merged = merge3(
+            &Document::parse(base).unwrap(),
+            &Document::parse(ours).unwrap(),
+            &Document::parse(theirs).unwrap(),
+        )
+        .unwrap();
+        let keys = merged
+            .conflicts()
+            .iter()
+            .map(|c| c.key().to_string())
+            .collect();
+        (format!("{}", merged.document()), keys)
+    }
+
+    #[test]
+    fn clean() {
+        let base = "# app\na=1\nb=2\nc=3\nd=4\n";
+        // ours reorders and edits a, theirs edits c, removes d and adds e
+        let ours = "# app\nc=3\n\n# first\na=11\nb=2\nd=4\n";
+        let theirs = "a=1\nb=2\nc=33\ne=5\n";
+        let (text, conflicts) = merge(base, ours, theirs);
+        assert!(conflicts.is_empty());
+        assert_eq!(text, "# app\nc=33\ne=5\n\n# first\na=11\nb=2\n");
+
+        // the same change on both sides
+        let (text, conflicts) = merge("a=1\n", "a=2\n", "a = 2\n");
+        assert!(conflicts.is_empty());
+        assert_eq!(text, "a=2\n");
+    }
+
+    #[test]
+    fn conflicts() {
+        let base = "a=1\nb=2\nc=3";
+        let ours = "a=11\r\nb=2\r\n";
+        let theirs = "a=12\nb=2\nc=33\n";
+        let (text, conflicts) = merge(base, ours, theirs);
+        assert_eq!(conflicts, vec!["a", "c"]);
+        assert_eq!(
+            text,
+            "<<<<<<< ours\r\na=11\r\n=======\r\na=12\r\n>>>>>>> theirs\r\nb=2\r\n<<<<<<< ours\r\n=======\r\nc=33\r\n>>>>>>> theirs\r\n"
+        );
+    }
+}
@@ -0,0 +1,459 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use super::file::{read_optional, write_atomic};
+use super::query::join_prefix;
+use super::tree::{normalize_prefix, strip_segment_prefix};
+use super::{Document, Properties, Result};
+
+/// A rule of a `Migration`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// Renames key `from` to `to`, when `to` exists already `from` is
+    /// dropped instead.
+    Rename { from: String, to: String },
+    /// Renames `from` and every key under it, e.g. from `old.cache` to
+    /// `cache` turns `old.cache.size` into `cache.size`, key by key as
+    /// `Rename`.
+    RenamePrefix { from: String, to: String },
+    /// Removes the key.
+    Drop(String),
+    /// Splits the value of `from` on `separator` into the keys `to`, e.g.
+    /// `db.address=host:5432` into `db.host` and `db.port`. Skipped if the
+    /// number of parts doesn't match or one of `to` exists already.
+    Split {
+        from: String,
+        separator: String,
+        to: Vec<String>,
+    },
+    /// Joins the values of the keys `from` with `separator` into `to`, the
+    /// reverse of `Split`. Skipped unless all the keys exist, or if `to`
+    /// exists already.
+    Join {
+        from: Vec<String>,
+        separator: String,
+        to: String,
+    },
+    /// Sets the key unless it exists.
+    SetIfAbsent { key: String, value: String },
+}
+
+/// What a `Migration` did, one line per applied or skipped rule, e.g.
+/// `rename 'db.maxSize' to 'db.pool.size'`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    applied: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl MigrationReport {
+    pub fn applied(&self) -> &[String] {
+        &self.applied
+    }
+
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Returns whether any rule changed anything.
+    pub fn is_changed(&self) -> bool {
+        !self.applied.is_empty()
+    }
+}
+
+impl Display for MigrationReport {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        for line in &self.applied {
+            writeln!(fmt, "{}", line)?;
+        }
+        for line in &self.skipped {
+            writeln!(fmt, "skip {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// What the rules operate on, keeping the layout where the target has one.
+trait Target {
+    fn get(&mut self, key: &str) -> Option<String>;
+    fn keys(&mut self) -> Vec<String>;
+    fn set(&mut self, key: &str, value: &str) -> Result<()>;
+    fn set_after(&mut self, after: &str, key: &str, value: &str) -> Result<()>;
+    fn remove(&mut self, key: &str);
+    fn rename(&mut self, from: &str, to: &str) -> Result<()>;
+}
+
+impl Target for Properties {
+    fn get(&mut self, key: &str) -> Option<String> {
+        self.data.lock().unwrap().get(key).cloned()
+    }
+
+    fn keys(&mut self) -> Vec<String> {
+        let mut keys: Vec<String> = self.data.lock().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        Properties::set(self, key, value);
+        Ok(())
+    }
+
+    fn set_after(&mut self, _: &str, key: &str, value: &str) -> Result<()> {
+        Properties::set(self, key, value);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) {
+        Properties::remove(self, key);
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        if let Some(value) = Properties::remove(self, from) {
+            Properties::set(self, to, &value);
+        }
+        Ok(())
+    }
+}
+
+impl Target for Document {
+    fn get(&mut self, key: &str) -> Option<String> {
+        Document::get(self, key).map(String::from)
+    }
+
+    fn keys(&mut self) -> Vec<String> {
+        Document::keys(self).into_iter().map(String::from).collect()
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        Document::set(self, key, value)
+    }
+
+    fn set_after(&mut self, after: &str, key: &str, value: &str) -> Result<()> {
+        Document::set_after(self, after, key, value)
+    }
+
+    fn remove(&mut self, key: &str) {
+        Document::remove(self, key);
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        Document::rename(self, from, to).map(|_| ())
+    }
+}
+
+fn quote(keys: &[String]) -> String {
+    let quoted: Vec<String> = keys.iter().map(|k| format!("'{}'", k)).collect();
+    quoted.join(", ")
+}
+
+fn rename<T: Target>(
+    target: &mut T,
+    from: &str,
+    to: &str,
+    report: &mut MigrationReport,
+) -> Result<()> {
+    if from == to || target.get(from).is_none() {
+        return Ok(());
+    }
+    if target.get(to).is_some() {
+        target.remove(from);
+        report
+            .applied
+            .push(format!("drop '{}', '{}' exists already", from, to));
+        return Ok(());
+    }
+    target.rename(from, to)?;
+    report
+        .applied
+        .push(format!("rename '{}' to '{}'", from, to));
+    Ok(())
+}
+
+/// Rewrites keys and values according to a list of rules applied in order,
+/// e.g. to migrate configuration files between versions.
+pub struct Migration {
+    rules: Vec<Rule>,
+}
+
+impl Migration {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+
+    fn run<T: Target>(&self, target: &mut T) -> Result<MigrationReport> {
+        let mut report = MigrationReport::default();
+        for rule in &self.rules {
+            match rule {
+                Rule::Rename { from, to } => rename(target, from, to, &mut report)?,
+                Rule::RenamePrefix { from, to } => {
+                    for key in target.keys() {
+                        let rest = if key == normalize_prefix(from) {
+                            Some("")
+                        } else {
+                            strip_segment_prefix(&key, from)
+                        };
+                        if let Some(rest) = rest {
+                            rename(target, &key, &join_prefix(to, rest), &mut report)?;
+                        }
+                    }
+                }
+                Rule::Drop(key) => {
+                    if target.get(key).is_some() {
+                        target.remove(key);
+                        report.applied.push(format!("drop '{}'", key));
+                    }
+                }
+                Rule::Split {
+                    from,
+                    separator,
+                    to,
+                } => {
+                    let value = match target.get(from) {
+                        Some(value) => value,
+                        None => continue,
+                    };
+                    let parts: Vec<&str> = value.split(separator.as_str()).collect();
+                    if parts.len() != to.len() || to.is_empty() {
+                        report.skipped.push(format!(
+                            "split '{}' into {}, found {} parts",
+                            from,
+                            quote(to),
+                            parts.len()
+                        ));
+                        continue;
+                    }
+                    let existing: Vec<String> = to
+                        .iter()
+                        .filter(|k| *k != from && target.get(k).is_some())
+                        .cloned()
+                        .collect();
+                    if !existing.is_empty() {
+                        report.skipped.push(format!(
+                            "split '{}' into {}, {} exists already",
+                            from,
+                            quote(to),
+                            quote(&existing)
+                        ));
+                        continue;
+                    }
+                    // the first part takes the place of the old key
+                    let first = &to[0];
+                    if first != from {
+                        target.rename(from, first)?;
+                    }
+                    target.set(first, parts[0])?;
+                    for i in 1..to.len() {
+                        target.set_after(&to[i - 1], &to[i], parts[i])?;
+                    }
+                    report
+                        .applied
+                        .push(format!("split '{}' into {}", from, quote(to)));
+                }
+                Rule::Join {
+                    from,
+                    separator,
+                    to,
+                } => {
+                    let values: Vec<Option<String>> = from.iter().map(|k| target.get(k)).collect();
+                    if values.iter().all(Option::is_none) {
+                        continue;
+                    }
+                    let missing: Vec<String> = from
+                        .iter()
+                        .zip(&values)
+                        .filter(|(_, v)| v.is_none())
+                        .map(|(k, _)| k.clone())
+                        .collect();
+                    if !missing.is_empty() {
+                        report.skipped.push(format!(
+                            "join {} into '{}', missing {}",
+                            quote(from),
+                            to,
+                            quote(&missing)
+                        ));
+                        continue;
+                    }
+                    if !from.contains(to) && target.get(to).is_some() {
+                        report.skipped.push(format!(
+                            "join {} into '{}', '{}' exists already",
+                            quote(from),
+                            to,
+                            to
+                        ));
+                        continue;
+                    }
+                    let value = values
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<String>>()
+                        .join(separator);
+                    // the joined key takes the place of the first one
+                    if &from[0] != to {
+                        target.rename(&from[0], to)?;
+                    }
+                    for key in from.iter().filter(|k| *k != to) {
+                        target.remove(key);
+                    }
+                    target.set(to, &value)?;
+                    report
+                        .applied
+                        .push(format!("join {} into '{}'", quote(from), to));
+                }
+                Rule::SetIfAbsent { key, value } => {
+                    if target.get(key).is_none() {
+                        target.set(key, value)?;
+                        report.applied.push(format!("set '{}'", key));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Applies the rules to `prop`, listeners are notified once with all the
+    /// changes.
+    pub fn apply(&self, prop: &mut Properties) -> Result<MigrationReport> {
+        prop.batch(|p| self.run(p))
+    }
+
+    /// Applies the rules to `doc`, keeping comments and the order of the
+    /// keys, renamed keys stay in place.
+    pub fn apply_document(&self, doc: &mut Document) -> Result<MigrationReport> {
+        self.run(doc)
+    }
+
+    /// Applies the rules to the file at `path` as `apply_document`, the file
+    /// is rewritten atomically if anything changed. A missing file is left
+    /// alone.
+    pub fn apply_file<P: AsRef<Path>>(&self, path: P) -> Result<MigrationReport> {
+        let path = path.as_ref();
+        let data = match read_optional(path)? {
+            Some(data) => data,
+            None => return Ok(MigrationReport::default()),
+        };
+        let mut doc = Document::parse_bytes(&data).map_err(|e| e.with_path(path))?;
+        let report = self.apply_document(&mut doc)?;
+        if report.is_changed() {
+            write_atomic(path, doc.to_string().as_bytes())?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Migration, Rule};
+    use crate::testutil::TempPath;
+    use crate::{Document, Properties};
+
+    fn migration() -> Migration {
+        Migration::new(vec![
+            Rule::Rename {
+                from: "db.maxSize".to_string(),
+                to: "db.pool.size".to_string(),
+            },
+            Rule::RenamePrefix {
+                from: "old.cache".to_string(),
+                to: "cache".to_string(),
+            },
+            Rule::Drop("legacy".to_string()),
+            Rule::Split {
+                from: "db.address".to_string(),
+                separator: ":".to_string(),
+                to: vec!["db.host".to_string(), "db.port".to_string()],
+            },
+            Rule::Join {
+                from: vec!["user.first".to_string(), "user.last".to_string()],
+                separator: " ".to_string(),
+                to: "user.name".to_string(),
+            },
+            Rule::SetIfAbsent {
+                key: "db.pool.size".to_string(),
+                value: "8".to_string(),
+            },
+        ])
+    }
+
+    const TEXT: &str = "# database\ndb.address=db1:5432\ndb.maxSize=4\n\n# cache\nold.cache.size=10\nold.cache.ttl=60\ncache.ttl=30\nlegacy=true\nuser.first=John\nuser.last=Doe\n";
+
+    #[test]
+    fn document() {
+        let mut doc = Document::parse(TEXT).unwrap();
+        let report = migration().apply_document(&mut doc).unwrap();
+        assert_eq!(
+            doc.to_string(),
+            "# database\ndb.host=db1\ndb.port=5432\ndb.pool.size=4\n\n# cache\ncache.size=10\ncache.ttl=30\nuser.name=John Doe\n"
+        );
+        assert_eq!(
+            report.applied(),
+            [
+                "rename 'db.maxSize' to 'db.pool.size'",
+                "rename 'old.cache.size' to 'cache.size'",
+                "drop 'old.cache.ttl', 'cache.ttl' exists already",
+                "drop 'legacy'",
+                "split 'db.address' into 'db.host', 'db.port'",
+                "join 'user.first', 'user.last' into 'user.name'",
+            ]
+        );
+        assert!(report.skipped().is_empty());
+
+        // applying again changes nothing
+        let report = migration().apply_document(&mut doc).unwrap();
+        assert!(!report.is_changed());
+    }
+
+    #[test]
+    fn properties() {
+        let mut prop = Properties::new();
+        prop.load("db.address=db1\nuser.first=John\n".as_bytes())
+            .unwrap();
+        let report = migration().apply(&mut prop).unwrap();
+        assert_eq!(prop.get("db.address").unwrap(), "db1");
+        assert_eq!(prop.get("user.first").unwrap(), "John");
+        assert_eq!(prop.get("db.pool.size").unwrap(), "8");
+        assert_eq!(report.applied(), ["set 'db.pool.size'"]);
+        assert_eq!(
+            report.to_string(),
+            "set 'db.pool.size'\nskip split 'db.address' into 'db.host', 'db.port', found 1 parts\nskip join 'user.first', 'user.last' into 'user.name', missing 'user.last'\n"
+        );
+    }
+
+    #[test]
+    fn collisions() {
+        let mut prop = Properties::new();
+        prop.load(
+            "db.address=db1:5432\ndb.port=6543\nuser.first=John\nuser.last=Doe\nuser.name=jd\n"
+                .as_bytes(),
+        )
+        .unwrap();
+        let report = migration().apply(&mut prop).unwrap();
+        assert_eq!(prop.get("db.address").unwrap(), "db1:5432");
+        assert_eq!(prop.get("db.port").unwrap(), "6543");
+        assert_eq!(prop.get("db.host"), None);
+        assert_eq!(prop.get("user.first").unwrap(), "John");
+        assert_eq!(prop.get("user.name").unwrap(), "jd");
+        assert_eq!(
+            report.skipped(),
+            [
+                "split 'db.address' into 'db.host', 'db.port', 'db.port' exists already",
+                "join 'user.first', 'user.last' into 'user.name', 'user.name' exists already",
+            ]
+        );
+    }
+
+    #[test]
+    fn file() {
+        let path = TempPath::new("migrate");
+        fs::write(&path, TEXT).unwrap();
+        let report = migration().apply_file(&path).unwrap();
+        assert_eq!(report.applied().len(), 6);
+        let mut prop = Properties::new();
+        prop.load(fs::read(&path).unwrap().as_slice()).unwrap();
+        assert_eq!(prop.get("db.port").unwrap(), "5432");
+        assert_eq!(prop.len(), 6);
+        fs::remove_file(&path).unwrap();
+    }
+}
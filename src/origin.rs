@@ -0,0 +1,163 @@
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+
+use super::{Properties, Result};
+
+/// Where an entry came from: the source name and the range of its logical
+/// line, from the first to the last character (1-based, counted in
+/// characters). The line numbers are 0 if the position is unknown, e.g. for
+/// values taken from the environment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Origin {
+    source: Option<String>,
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+impl Origin {
+    pub(crate) fn new(source: Option<String>, start: (usize, usize), end: (usize, usize)) -> Self {
+        Self { source, start, end }
+    }
+
+    pub(crate) fn named(source: String) -> Self {
+        Self::new(Some(source), (0, 0), (0, 0))
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn line(&self) -> usize {
+        self.start.0
+    }
+
+    pub fn column(&self) -> usize {
+        self.start.1
+    }
+
+    pub fn end_line(&self) -> usize {
+        self.end.0
+    }
+
+    pub fn end_column(&self) -> usize {
+        self.end.1
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        let source = self.source.as_deref().unwrap_or("<unknown>");
+        if self.start.0 == 0 {
+            return write!(fmt, "{}", source);
+        }
+        write!(
+            fmt,
+            "{}:{}:{}-{}:{}",
+            source, self.start.0, self.start.1, self.end.0, self.end.1
+        )
+    }
+}
+
+impl Properties {
+    /// Returns where the value of `key` came from, only recorded by
+    /// `load_with` with the `origins` option enabled, and cleared once the
+    /// key is set or removed directly.
+    pub fn origin(&mut self, key: &str) -> Option<Origin> {
+        let data = self.data.lock().unwrap();
+        let key = self.resolve_key(&data, key)?;
+        self.origins.get(&key).cloned()
+    }
+
+    /// Writes every key with its effective value and origin in lexical order,
+    /// one per line, e.g. `db.url=jdbc:h2:mem (app.properties:3:1-3:19)`.
+    /// Redacted values are masked.
+    pub fn explain<W: Write>(&mut self, mut writer: W) -> Result<()> {
+        let data = self.data.lock().unwrap();
+        let mut keys: Vec<&String> = data.keys().collect();
+        keys.sort();
+        for k in keys {
+            let v = self.masked(k, &data[k]);
+            match self.origins.get(k) {
+                Some(origin) => writeln!(writer, "{}={} ({})", k, v, origin)?,
+                None => writeln!(writer, "{}={} (<unknown>)", k, v)?,
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Origin, Properties};
+    use crate::ReadOption;
+
+    #[test]
+    fn positions() {
+        let input = "# comment\n\na0=b\r\n  a1 = c\\\n     d\ra2=你好\\\r\n 🌐\n#a3=x";
+        let mut opt = ReadOption::default();
+        opt.source("app.properties".to_string());
+        opt.origins(true);
+
+        let mut prop = Properties::new();
+        prop.load_with(input.as_bytes(), &opt).unwrap();
+        let cases = vec![
+            ("a0", (3, 1), (3, 4)),
+            ("a1", (4, 3), (5, 6)),
+            ("a2", (6, 1), (7, 2)),
+        ];
+        for (key, start, end) in cases {
+            let origin = prop.origin(key).unwrap();
+            assert_eq!(origin.source(), Some("app.properties"));
+            assert_eq!((origin.line(), origin.column()), start, "{}", key);
+            assert_eq!((origin.end_line(), origin.end_column()), end, "{}", key);
+        }
+        assert_eq!(prop.origin("a3"), None);
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut prop = Properties::new();
+        prop.load("a0=b\n".as_bytes()).unwrap();
+        assert_eq!(prop.origin("a0"), None);
+
+        let mut opt = ReadOption::default();
+        opt.origins(true);
+        prop.load_with("a0=c\na1=d\n".as_bytes(), &opt).unwrap();
+        assert_eq!(
+            format!("{}", prop.origin("a1").unwrap()),
+            "<unknown>:2:1-2:4"
+        );
+
+        prop.set("a0", "e");
+        prop.remove("a1");
+        assert_eq!(prop.origin("a0"), None);
+        assert_eq!(prop.origin("a1"), None);
+    }
+
+    #[test]
+    fn explain() {
+        let mut opt = ReadOption::default();
+        opt.source("app.properties".to_string());
+        opt.origins(true);
+
+        let mut prop = Properties::new();
+        prop.load_with("db.url=jdbc:h2:mem\n".as_bytes(), &opt)
+            .unwrap();
+        prop.set("db.user", "root");
+        prop.set("db.password", "s3cret");
+        prop.redact_pattern("*password");
+        prop.set("db.pool", "8");
+        prop.origins.insert(
+            "db.pool".to_string(),
+            Origin::named("environment".to_string()),
+        );
+
+        let mut buff = Vec::new();
+        prop.explain(&mut buff).unwrap();
+        assert_eq!(
+            String::from_utf8(buff).unwrap(),
+            "db.password=****** (<unknown>)\ndb.pool=8 (environment)\ndb.url=jdbc:h2:mem (app.properties:1:1-1:18)\ndb.user=root (<unknown>)\n"
+        );
+    }
+}
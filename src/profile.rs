@@ -0,0 +1,191 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use super::{LayeredProperties, Properties, PropertiesError, ReadOption, Result};
+
+const ON_PROFILE: &str = "spring.config.activate.on-profile";
+
+// Split a multi-document file on the "#---" and "!---" separator lines,
+// returns every document with the number of lines before it.
+fn split_documents(data: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut first_line = 0;
+    let mut offset = 0;
+    let mut line = 0;
+
+    while offset < data.len() {
+        let end = match data[offset..].iter().position(|&c| c == b'\n') {
+            Some(pos) => offset + pos + 1,
+            None => data.len(),
+        };
+        let text = data[offset..end]
+            .strip_suffix(b"\n")
+            .unwrap_or(&data[offset..end]);
+        let text = text.strip_suffix(b"\r").unwrap_or(text);
+        line += 1;
+        if text == b"#---" || text == b"!---" {
+            result.push((first_line, &data[start..offset]));
+            start = end;
+            first_line = line;
+        }
+        offset = end;
+    }
+    result.push((first_line, &data[start..]));
+    result
+}
+
+// Check a `spring.config.activate.on-profile` expression, a list of
+// alternatives separated by ',' or '|', each of which a list of profiles
+// separated by '&' that must all match, a profile prefixed with '!' matches
+// when it's not active.
+fn profile_matches(expr: &str, profiles: &[&str]) -> bool {
+    expr.split([',', '|']).any(|alt| {
+        alt.split('&').all(|term| {
+            let term = term.trim();
+            match term.strip_prefix('!') {
+                Some(name) => !profiles.contains(&name.trim()),
+                None => !term.is_empty() && profiles.contains(&term),
+            }
+        })
+    })
+}
+
+fn load_documents(layered: &mut LayeredProperties, path: &Path, profiles: &[&str]) -> Result<()> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(PropertiesError::from(e).with_path(path)),
+    };
+    let name = path.display().to_string();
+    let documents = split_documents(&data);
+    let multiple = documents.len() > 1;
+
+    for (index, (skip_lines, doc)) in documents.into_iter().enumerate() {
+        let mut opt = ReadOption::default();
+        opt.source(name.clone());
+        opt.origins(true);
+        opt.skip_lines = skip_lines;
+
+        let mut prop = Properties::new();
+        prop.load_with(doc, &opt).map_err(|e| e.with_path(path))?;
+        if let Some(expr) = prop.remove(ON_PROFILE) {
+            if !profile_matches(&expr, profiles) {
+                continue;
+            }
+        }
+        if multiple {
+            layered.push(format!("{} (document {})", name, index + 1), prop);
+        } else {
+            layered.push(name.clone(), prop);
+        }
+    }
+    Ok(())
+}
+
+/// Loads Spring style profile files from `dir`, i.e. `{base}.properties`
+/// followed by `{base}-{profile}.properties` for every active profile in
+/// order, the later ones take precedence. Missing files are skipped.
+///
+/// A file could contain multiple documents separated by `#---` or `!---`
+/// lines, a document with `spring.config.activate.on-profile` is only used
+/// when the expression matches the active profiles.
+pub fn load_profiles<P: AsRef<Path>>(
+    dir: P,
+    base: &str,
+    profiles: &[&str],
+) -> Result<LayeredProperties> {
+    let dir = dir.as_ref();
+    let mut layered = LayeredProperties::new();
+    load_documents(
+        &mut layered,
+        &dir.join(format!("{}.properties", base)),
+        profiles,
+    )?;
+    for profile in profiles {
+        load_documents(
+            &mut layered,
+            &dir.join(format!("{}-{}.properties", base, profile)),
+            profiles,
+        )?;
+    }
+    Ok(layered)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{load_profiles, profile_matches, split_documents};
+    use crate::testutil::TempPath;
+
+    #[test]
+    fn documents() {
+        let data = "a=1\n#---\nb=2\r\n!---\r\n#----\nc=3\n#---";
+        let docs: Vec<(usize, String)> = split_documents(data.as_bytes())
+            .into_iter()
+            .map(|(line, doc)| (line, String::from_utf8(doc.to_vec()).unwrap()))
+            .collect();
+        assert_eq!(
+            docs,
+            vec![
+                (0, "a=1\n".to_string()),
+                (2, "b=2\r\n".to_string()),
+                (4, "#----\nc=3\n".to_string()),
+                (7, "".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let cases = vec![
+            ("dev", true),
+            ("prod", false),
+            ("prod, dev", true),
+            ("!prod", true),
+            ("!dev", false),
+            ("dev & cloud", true),
+            ("dev & prod", false),
+            ("prod | cloud", true),
+            ("", false),
+        ];
+        for &(expr, expected) in &cases {
+            assert_eq!(
+                profile_matches(expr, &["dev", "cloud"]),
+                expected,
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn profiles() {
+        let dir = TempPath::new("profile");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("application.properties"),
+            "server.port=8080\nlog.level=info\n#---\nspring.config.activate.on-profile=prod\nlog.level=warn\n#---\nspring.config.activate.on-profile=!prod\ndb.url=jdbc:h2:mem\n",
+        )
+        .unwrap();
+        fs::write(dir.join("application-dev.properties"), "log.level=debug\n").unwrap();
+        fs::write(dir.join("application-cloud.properties"), "server.port=80\n").unwrap();
+
+        let mut prop = load_profiles(&dir, "application", &["dev", "cloud", "missing"]).unwrap();
+        assert_eq!(prop.len(), 3);
+        assert_eq!(prop.get("server.port").unwrap(), "80");
+        assert_eq!(prop.get("log.level").unwrap(), "debug");
+        assert_eq!(prop.get("db.url").unwrap(), "jdbc:h2:mem");
+        assert_eq!(prop.get("spring.config.activate.on-profile"), None);
+        assert_eq!(prop.layers().len(), 4);
+
+        let origin = prop.origin("db.url").unwrap();
+        assert_eq!(origin.line(), 8);
+
+        let mut prop = load_profiles(&dir, "application", &["prod"]).unwrap();
+        assert_eq!(prop.get("log.level").unwrap(), "warn");
+        assert_eq!(prop.get("db.url"), None);
+    }
+}
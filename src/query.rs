@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use super::tree::{normalize_prefix, strip_segment_prefix};
+use super::{Change, Origin, Properties, PropertiesError, Result};
+
+// Match text against a glob pattern, where '*' matches any sequence of
+// characters (including '.') and '?' matches exactly one character.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || (p[pi] != '*' && p[pi] == t[ti])) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            // backtrack, let the last '*' swallow one more character
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+pub(crate) fn join_prefix(prefix: &str, rest: &str) -> String {
+    let prefix = normalize_prefix(prefix);
+    if prefix.is_empty() {
+        rest.to_string()
+    } else if rest.is_empty() {
+        prefix.to_string()
+    } else {
+        format!("{}.{}", prefix, rest)
+    }
+}
+
+impl Properties {
+    /// Returns the keys matching the glob `pattern` in lexical order, `*`
+    /// matches any sequence of characters and `?` exactly one.
+    pub fn keys_matching(&mut self, pattern: &str) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        let mut result: Vec<String> = data
+            .keys()
+            .filter(|k| glob_match(pattern, k))
+            .cloned()
+            .collect();
+        result.sort();
+        result
+    }
+
+    /// Returns the keys matching the regular expression in lexical order.
+    #[cfg(feature = "regex")]
+    pub fn keys_matching_regex(&mut self, re: &regex::Regex) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        let mut result: Vec<String> = data.keys().filter(|k| re.is_match(k)).cloned().collect();
+        result.sort();
+        result
+    }
+
+    /// Removes `prefix` and every key under it, returns the removed entries
+    /// in lexical order.
+    pub fn remove_prefix(&mut self, prefix: &str) -> Vec<(String, String)> {
+        let mut data = self.data.lock().unwrap();
+        let mut keys: Vec<String> = data
+            .keys()
+            .filter(|k| strip_segment_prefix(k, prefix).is_some() || *k == normalize_prefix(prefix))
+            .cloned()
+            .collect();
+        keys.sort();
+        let removed: Vec<(String, String)> = keys
+            .into_iter()
+            .map(|k| {
+                let v = data.remove(&k).unwrap();
+                self.origins.remove(&k);
+                (k, v)
+            })
+            .collect();
+        drop(data);
+        self.batch(|p| {
+            for (k, v) in &removed {
+                p.record_change(Change::new(k.clone(), Some(v.clone()), None));
+            }
+        });
+        removed
+    }
+
+    /// Moves `from` and every key under it to `to`, e.g. renaming prefix
+    /// `old.cache` to `cache` turns `old.cache.size` into `cache.size`.
+    ///
+    /// Returns the `(old, new)` key pairs in lexical order. Nothing is changed
+    /// if any new key would overwrite an existing key which is not moved.
+    pub fn rename_prefix(&mut self, from: &str, to: &str) -> Result<Vec<(String, String)>> {
+        let mut data = self.data.lock().unwrap();
+        let mut renames: Vec<(String, String)> = Vec::new();
+        for k in data.keys() {
+            let rest = if k == normalize_prefix(from) {
+                Some("")
+            } else {
+                strip_segment_prefix(k, from)
+            };
+            if let Some(rest) = rest {
+                renames.push((k.clone(), join_prefix(to, rest)));
+            }
+        }
+        renames.sort();
+
+        let moved: HashMap<&str, &str> = renames
+            .iter()
+            .map(|(o, n)| (o.as_str(), n.as_str()))
+            .collect();
+        for (_, new) in &renames {
+            if data.contains_key(new) && !moved.contains_key(new.as_str()) {
+                return Err(PropertiesError::new(format!(
+                    "rename prefix collision, key '{}' already exists",
+                    new
+                )));
+            }
+        }
+
+        let values: Vec<(String, Option<Origin>)> = renames
+            .iter()
+            .map(|(o, _)| (data.remove(o).unwrap(), self.origins.remove(o)))
+            .collect();
+        let mut changes: Vec<Change> = renames
+            .iter()
+            .zip(&values)
+            .map(|((old, _), (v, _))| Change::new(old.clone(), Some(v.clone()), None))
+            .collect();
+        for ((_, new), (v, origin)) in renames.iter().zip(values) {
+            let previous = data.insert(new.clone(), v.clone());
+            changes.push(Change::new(new.clone(), previous, Some(v)));
+            match origin {
+                Some(origin) => self.origins.insert(new.clone(), origin),
+                None => self.origins.remove(new),
+            };
+        }
+        drop(data);
+        self.batch(|p| {
+            for change in changes {
+                p.record_change(change);
+            }
+        });
+        Ok(renames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+    use crate::testutil::props;
+
+    const DATA: &str = "old.cache=on\nold.cache.size=64\nold.cache.ttl=30\nold.cachex=keep\ndebug.sql=true\ndebug.http.trace=false\nserver.port=8080\n";
+
+    #[test]
+    fn glob() {
+        let cases = vec![
+            ("*", "", true),
+            ("*", "a.b", true),
+            ("a.*", "a.b.c", true),
+            ("a.*", "a", false),
+            ("a?c", "abc", true),
+            ("a?c", "ac", false),
+            ("*.port", "server.port", true),
+            ("*pass*", "db.password", true),
+            ("*pass*", "db.user", false),
+            ("a*b*c", "axxbyyc", true),
+            ("a*b*c", "axxbyy", false),
+            ("你*", "你好", true),
+        ];
+        for &(pattern, text, expected) in &cases {
+            assert_eq!(glob_match(pattern, text), expected, "{} {}", pattern, text);
+        }
+    }
+
+    #[test]
+    fn keys_matching() {
+        let mut prop = props(DATA);
+        assert_eq!(
+            prop.keys_matching("old.cache.*"),
+            vec!["old.cache.size", "old.cache.ttl"]
+        );
+        assert_eq!(prop.keys_matching("*.port"), vec!["server.port"]);
+        assert!(prop.keys_matching("none.*").is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn keys_matching_regex() {
+        let mut prop = props(DATA);
+        let re = regex::Regex::new(r"^debug\.[a-z]+$").unwrap();
+        assert_eq!(prop.keys_matching_regex(&re), vec!["debug.sql"]);
+    }
+
+    #[test]
+    fn remove_prefix() {
+        let mut prop = props(DATA);
+        let removed = prop.remove_prefix("debug.");
+        assert_eq!(
+            removed,
+            vec![
+                ("debug.http.trace".to_string(), "false".to_string()),
+                ("debug.sql".to_string(), "true".to_string()),
+            ]
+        );
+        assert_eq!(prop.len(), 5);
+        assert!(prop.remove_prefix("debug").is_empty());
+    }
+
+    #[test]
+    fn rename_prefix() {
+        let mut prop = props(DATA);
+        let renamed = prop.rename_prefix("old.cache", "cache").unwrap();
+        assert_eq!(
+            renamed,
+            vec![
+                ("old.cache".to_string(), "cache".to_string()),
+                ("old.cache.size".to_string(), "cache.size".to_string()),
+                ("old.cache.ttl".to_string(), "cache.ttl".to_string()),
+            ]
+        );
+        assert_eq!(prop.len(), 7);
+        assert_eq!(prop.get("cache").unwrap(), "on");
+        assert_eq!(prop.get("cache.size").unwrap(), "64");
+        assert_eq!(prop.get("old.cachex").unwrap(), "keep");
+        assert_eq!(prop.get("old.cache.size"), None);
+
+        // overwriting a key which is moved away as well is not a collision
+        let mut prop = props(DATA);
+        prop.set("old.size", "1");
+        prop.rename_prefix("old", "old.cache").unwrap();
+        assert_eq!(prop.get("old.cache.size").unwrap(), "1");
+        assert_eq!(prop.get("old.cache.cache.size").unwrap(), "64");
+    }
+
+    #[test]
+    fn rename_prefix_collision() {
+        let mut prop = props(DATA);
+        prop.set("cache.size", "128");
+        match prop.rename_prefix("old.cache", "cache") {
+            Ok(_) => panic!("rename should fail"),
+            Err(e) => {
+                let msg = format!("{}", e);
+                assert!(msg.starts_with("rename prefix collision"), "{}", msg);
+            }
+        }
+        assert_eq!(prop.get("cache.size").unwrap(), "128");
+        assert_eq!(prop.get("old.cache.size").unwrap(), "64");
+    }
+}
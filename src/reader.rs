@@ -1,6 +1,70 @@
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 
-use super::{Properties, PropertiesError, Result};
+use super::include::{INCLUDE, INCLUDE_OPTIONAL};
+use super::{Change, Origin, Properties, PropertiesError, Result};
+
+#[derive(Clone)]
+pub struct ReadOption {
+    pub(crate) source: Option<String>,
+    origins: bool,
+    pub(crate) includes: bool,
+    pub(crate) max_include_depth: usize,
+    pub(crate) include_root: Option<PathBuf>,
+    // number of lines before the input, when it's a part of the source
+    pub(crate) skip_lines: usize,
+    // directory of the file being loaded, and the chain of files including it
+    pub(crate) base_dir: Option<PathBuf>,
+    pub(crate) include_stack: Vec<PathBuf>,
+    pub(crate) include_depth: usize,
+}
+
+impl ReadOption {
+    pub fn source(&mut self, val: String) -> &Self {
+        self.source = Some(val);
+        self
+    }
+
+    pub fn origins(&mut self, val: bool) -> &Self {
+        self.origins = val;
+        self
+    }
+
+    /// Processes `include` and `include.optional` entries, whose values are
+    /// comma separated files loaded in place, relative to the including file.
+    pub fn includes(&mut self, val: bool) -> &Self {
+        self.includes = val;
+        self
+    }
+
+    pub fn max_include_depth(&mut self, val: usize) -> &Self {
+        self.max_include_depth = val;
+        self
+    }
+
+    /// Restricts included files to those inside directory `val`.
+    pub fn include_root(&mut self, val: PathBuf) -> &Self {
+        self.include_root = Some(val);
+        self
+    }
+}
+
+impl Default for ReadOption {
+    fn default() -> Self {
+        Self {
+            source: None,
+            origins: false,
+            includes: false,
+            max_include_depth: 8,
+            include_root: None,
+            skip_lines: 0,
+            base_dir: None,
+            include_stack: Vec::new(),
+            include_depth: 0,
+        }
+    }
+}
 
 fn decode_unicode(data: &[u8]) -> Result<u32> {
     let mut val: u32 = 0;
@@ -102,23 +166,70 @@ fn load_convert(data: &[u8]) -> Result<Vec<u8>> {
 // (\u0020, \u0009 and \u000c) from beginning of a "natural line".
 // Method returns the char length of the "logical line" and stores
 // the line in "line" field.
+//
+// The (line, column) positions of the first and last character of the
+// "logical line" are kept in "start" and "end" fields, both 1-based and
+// counted in characters.
 struct LineReader {
     buff: [u8; 8 * 1024],
     line: Vec<u8>,
     limit: usize,
     offset: usize,
+    row: usize,
+    column: usize,
+    after_cr: bool,
+    started: bool,
+    start: (usize, usize),
+    end: (usize, usize),
 }
 
 impl LineReader {
-    fn new() -> Self {
+    fn new(skip_lines: usize) -> Self {
         Self {
             buff: [0u8; 8 * 1024],
             line: Vec::with_capacity(1024),
             limit: 0,
             offset: 0,
+            row: skip_lines + 1,
+            column: 0,
+            after_cr: false,
+            started: false,
+            start: (0, 0),
+            end: (0, 0),
         }
     }
 
+    // Move forward over byte c and return its position, the trailing bytes
+    // of an UTF-8 sequence share the position of the leading one.
+    fn advance(&mut self, c: u8) -> (usize, usize) {
+        if c == b'\n' && self.after_cr {
+            self.after_cr = false;
+            return (self.row - 1, self.column);
+        }
+        self.after_cr = false;
+        if c & 0xC0 == 0x80 {
+            return (self.row, self.column);
+        }
+        if c == b'\r' || c == b'\n' {
+            let pos = (self.row, self.column + 1);
+            self.row += 1;
+            self.column = 0;
+            self.after_cr = c == b'\r';
+            return pos;
+        }
+        self.column += 1;
+        (self.row, self.column)
+    }
+
+    fn push(&mut self, c: u8, pos: (usize, usize)) {
+        self.line.push(c);
+        if !self.started {
+            self.started = true;
+            self.start = pos;
+        }
+        self.end = pos;
+    }
+
     fn read_line<R: Read>(&mut self, mut reader: R) -> Result<&Vec<u8>> {
         let mut c: u8;
         let mut skip_lf = false;
@@ -129,6 +240,7 @@ impl LineReader {
         let mut appended_line_begin = false;
 
         self.line.clear();
+        self.started = false;
         loop {
             if self.offset >= self.limit {
                 self.limit = reader.read(&mut self.buff)?;
@@ -144,6 +256,7 @@ impl LineReader {
             }
             c = self.buff[self.offset];
             self.offset = self.offset + 1;
+            let pos = self.advance(c);
 
             //println!(
             //    "Handle char {}, lf={} white={} newline={} comment={} backslash={}",
@@ -182,7 +295,7 @@ impl LineReader {
             }
 
             if c != b'\n' && c != b'\r' {
-                self.line.push(c);
+                self.push(c, pos);
                 if c == b'\\' {
                     preceding_backslash = !preceding_backslash;
                 } else {
@@ -196,6 +309,7 @@ impl LineReader {
                     skip_white_space = true;
                     preceding_backslash = false;
                     self.line.clear();
+                    self.started = false;
                     continue;
                 }
 
@@ -217,8 +331,38 @@ impl LineReader {
 }
 
 impl Properties {
-    pub fn load<R: Read>(&mut self, mut reader: R) -> Result<()> {
-        let mut lr = LineReader::new();
+    pub fn load<R: Read>(&mut self, reader: R) -> Result<()> {
+        self.load_with(reader, &ReadOption::default())
+    }
+
+    /// Loads the file at `path`, the path is used as the source name unless
+    /// set in `opt`, and is attached to any error.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P, opt: &ReadOption) -> Result<()> {
+        let path = path.as_ref();
+        let mut opt = opt.clone();
+        if opt.source.is_none() {
+            opt.source = Some(path.display().to_string());
+        }
+        let file = File::open(path).map_err(|e| PropertiesError::from(e).with_path(path))?;
+        if opt.includes {
+            let canonical = path
+                .canonicalize()
+                .map_err(|e| PropertiesError::from(e).with_path(path))?;
+            opt.base_dir = canonical.parent().map(Path::to_path_buf);
+            opt.include_stack.push(canonical);
+        }
+        self.load_with(BufReader::new(file), &opt)
+            .map_err(|e| e.with_path(path))
+    }
+
+    /// Loads the properties from `reader`, listeners are notified once with
+    /// all the changes, including the ones made before a failure.
+    pub fn load_with<R: Read>(&mut self, reader: R, opt: &ReadOption) -> Result<()> {
+        self.batch(|p| p.load_lines(reader, opt))
+    }
+
+    fn load_lines<R: Read>(&mut self, mut reader: R, opt: &ReadOption) -> Result<()> {
+        let mut lr = LineReader::new(opt.skip_lines);
         loop {
             match lr.read_line(&mut reader) {
                 Ok(l) => {
@@ -272,7 +416,23 @@ impl Properties {
 
                     let key: String = String::from_utf8(load_convert(&l[..key_len])?.to_vec())?;
                     let val: String = String::from_utf8(load_convert(&l[value_start..])?.to_vec())?;
-                    self.data.lock().unwrap().insert(key, val);
+                    if opt.includes && (key == INCLUDE || key == INCLUDE_OPTIONAL) {
+                        self.include(&val, key == INCLUDE_OPTIONAL, opt)?;
+                        continue;
+                    }
+                    // in relaxed mode update the stored key like `set`
+                    let key = {
+                        let data = self.data.lock().unwrap();
+                        self.resolve_key(&data, &key).unwrap_or(key)
+                    };
+                    if opt.origins {
+                        let origin = Origin::new(opt.source.clone(), lr.start, lr.end);
+                        self.origins.insert(key.clone(), origin);
+                    } else {
+                        self.origins.remove(&key);
+                    }
+                    let old = self.data.lock().unwrap().insert(key.clone(), val.clone());
+                    self.record_change(Change::new(key, old, Some(val)));
                 }
                 Err(e) => return Err(e),
             }
@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
+use std::io::Write;
+
+use super::query::glob_match;
+use super::tree::Scope;
+use super::{Properties, Result};
+
+pub(crate) const MASK: &str = "******";
+// like java.util.Properties.list()
+const LIST_WIDTH: usize = 40;
+
+#[derive(Default)]
+pub(crate) struct Redaction {
+    patterns: Vec<(String, Scope)>,
+    keys: HashSet<String>,
+}
+
+impl Redaction {
+    fn matches(&self, key: &str) -> bool {
+        if self.keys.contains(key) {
+            return true;
+        }
+        self.patterns
+            .iter()
+            .any(|(p, scope)| glob_match(p, &scope.original(key).to_lowercase()))
+    }
+
+    // The rules of a copy with scope, see `Properties::subtree`.
+    pub(crate) fn rebase(&self, scope: &Scope) -> Redaction {
+        Redaction {
+            patterns: self
+                .patterns
+                .iter()
+                .map(|(p, s)| (p.clone(), scope.compose(s)))
+                .collect(),
+            keys: self
+                .keys
+                .iter()
+                .filter_map(|k| scope.copy(k).map(String::from))
+                .collect(),
+        }
+    }
+}
+
+impl Properties {
+    /// Masks the values of the keys matching the glob `pattern`, e.g.
+    /// `*password*` or `*.token`, in `list`, `explain`, `export_journal`, in
+    /// error messages, in the `Debug` and `Display` output and in the `Debug`
+    /// output of a `Diff`. Patterns are matched ignoring case. `get` still returns the
+    /// real values.
+    pub fn redact_pattern(&mut self, pattern: &str) {
+        self.redaction
+            .patterns
+            .push((pattern.to_lowercase(), Scope::default()));
+    }
+
+    /// Same as `redact_pattern`, for exactly `key`.
+    pub fn redact_key(&mut self, key: &str) {
+        self.redaction.keys.insert(key.to_string());
+    }
+
+    pub fn is_redacted(&self, key: &str) -> bool {
+        self.redaction.matches(key)
+    }
+
+    // The value to show for key in messages and dumps.
+    pub(crate) fn masked<'a>(&self, key: &str, value: &'a str) -> &'a str {
+        if self.redaction.matches(key) {
+            MASK
+        } else {
+            value
+        }
+    }
+
+    // The entries sorted by key, with the redacted values masked.
+    fn redacted(&self) -> BTreeMap<String, String> {
+        let data = self.data.lock().unwrap();
+        data.iter()
+            .map(|(k, v)| {
+                let v = if self.redaction.matches(k) {
+                    MASK.to_string()
+                } else {
+                    v.clone()
+                };
+                (k.clone(), v)
+            })
+            .collect()
+    }
+
+    /// Prints the properties for debugging like `Properties.list()` in Java,
+    /// sorted by key, values longer than 40 characters are truncated and
+    /// redacted values are masked.
+    pub fn list<W: Write>(&mut self, mut writer: W) -> Result<()> {
+        writeln!(writer, "-- listing properties --")?;
+        for (k, v) in self.redacted() {
+            if v.chars().count() > LIST_WIDTH {
+                let head: String = v.chars().take(LIST_WIDTH - 3).collect();
+                writeln!(writer, "{}={}...", k, head)?;
+            } else {
+                writeln!(writer, "{}={}", k, v)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Debug for Properties {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_map().entries(self.redacted()).finish()
+    }
+}
+
+/// Formats like `toString()` in Java, e.g. `{a=1, password=******}`, sorted
+/// by key with the redacted values masked.
+impl Display for Properties {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
+        let entries: Vec<String> = self
+            .redacted()
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        write!(fmt, "{{{}}}", entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Properties;
+    use crate::testutil::props;
+
+    const DATA: &str = "db.url=jdbc:h2:mem\ndb.Password=s3cret\napi.token=abc\napi.tokens=1\nclient.secret.id=x\nsigning.key=k\nlong=0123456789012345678901234567890123456789x\n";
+
+    fn redacted() -> Properties {
+        let mut prop = props(DATA);
+        prop.redact_pattern("*password*");
+        prop.redact_pattern("*secret*");
+        prop.redact_pattern("*.token");
+        prop.redact_key("signing.key");
+        prop
+    }
+
+    #[test]
+    fn output() {
+        let mut prop = redacted();
+        assert_eq!(
+            format!("{}", prop),
+            "{api.token=******, api.tokens=1, client.secret.id=******, db.Password=******, db.url=jdbc:h2:mem, long=0123456789012345678901234567890123456789x, signing.key=******}"
+        );
+        let debug = format!("{:?}", prop);
+        assert!(debug.contains("\"db.Password\": \"******\""), "{}", debug);
+        assert!(!debug.contains("s3cret"), "{}", debug);
+
+        let mut out = Vec::new();
+        prop.list(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "-- listing properties --\napi.token=******\napi.tokens=1\nclient.secret.id=******\ndb.Password=******\ndb.url=jdbc:h2:mem\nlong=0123456789012345678901234567890123456...\nsigning.key=******\n"
+        );
+
+        assert_eq!(prop.get("db.Password").unwrap(), "s3cret");
+        assert!(prop.is_redacted("db.password"));
+        assert!(!prop.is_redacted("api.tokens"));
+    }
+
+    #[test]
+    fn copies() {
+        let mut prop = redacted();
+        let mut db = prop.subtree("db");
+        assert_eq!(format!("{}", db), "{Password=******, url=jdbc:h2:mem}");
+        let mut api = prop.subtree("api");
+        assert!(api.is_redacted("token"));
+        assert!(!api.is_redacted("tokens"));
+        let mut signing = prop.subtree("signing");
+        assert_eq!(format!("{}", signing), "{key=******}");
+        // rules of the copy apply to its own keys
+        signing.redact_pattern("other");
+        assert!(signing.is_redacted("other"));
+
+        let nested = prop.with_prefix("app").subtree("app.client");
+        assert_eq!(format!("{}", nested), "{secret.id=******}");
+        assert_eq!(format!("{}", signing.with_prefix("x")), "{x.key=******}");
+        assert!(signing.with_prefix("x").is_redacted("x.other"));
+        assert_eq!(db.get("Password").unwrap(), "s3cret");
+        assert_eq!(api.len(), 2);
+    }
+}
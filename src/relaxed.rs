@@ -0,0 +1,157 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::Properties;
+
+// Canonical form used by relaxed binding, like Spring: lower case with the
+// '-' and '_' dropped, while '.' is kept as the segment separator. In upper
+// case env style keys '_' separates the segments instead, so that
+// "db.maxSize", "db.max-size", "db.max_size" and "DB_MAXSIZE" all end up as
+// "db.maxsize", while "a.bc" and "ab.c" stay distinct.
+pub(crate) fn canonical(key: &str) -> String {
+    let env_style = key.chars().any(char::is_uppercase) && !key.chars().any(char::is_lowercase);
+    key.chars()
+        .filter_map(|c| match c {
+            '_' if env_style => Some('.'),
+            '-' | '_' => None,
+            c => Some(c),
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+impl Properties {
+    /// Enables or disables relaxed binding, in which keys are compared by
+    /// their canonical form, ignoring case, dashes, underscores and camelCase
+    /// boundaries within a segment. In upper case env style keys such as
+    /// `DB_MAXSIZE`, underscores separate the segments like dots.
+    ///
+    /// An exact match always wins. Otherwise `get`, `set`, `remove` and `load`
+    /// operate on the stored key with the same canonical form, so setting or
+    /// loading `DB_MAXSIZE` updates an existing `db.maxSize` instead of adding
+    /// a second entry.
+    pub fn relaxed(&mut self, val: bool) {
+        self.relaxed = val;
+    }
+
+    /// Returns the groups of stored keys which share the same canonical form
+    /// and so are ambiguous in relaxed mode, each group in lexical order.
+    pub fn relaxed_conflicts(&mut self) -> Vec<Vec<String>> {
+        let data = self.data.lock().unwrap();
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for k in data.keys() {
+            groups.entry(canonical(k)).or_default().push(k.clone());
+        }
+        groups
+            .into_values()
+            .filter(|g| g.len() > 1)
+            .map(|mut g| {
+                g.sort();
+                g
+            })
+            .collect()
+    }
+
+    // Find the stored key addressed by key, when several keys share the
+    // canonical form the lexically smallest one is used.
+    pub(crate) fn resolve_key(&self, data: &HashMap<String, String>, key: &str) -> Option<String> {
+        if data.contains_key(key) {
+            return Some(key.to_string());
+        }
+        if !self.relaxed {
+            return None;
+        }
+        let target = canonical(key);
+        data.keys()
+            .filter(|k| canonical(k) == target)
+            .min()
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical, Properties};
+
+    #[test]
+    fn canonical_form() {
+        let cases = vec![
+            ("db.maxSize", "db.maxsize"),
+            ("db.max-size", "db.maxsize"),
+            ("db.max_size", "db.maxsize"),
+            ("DB_MAXSIZE", "db.maxsize"),
+            ("SERVER_PORT2", "server.port2"),
+            ("a.bc", "a.bc"),
+            ("ab.c", "ab.c"),
+            ("Straße", "straße"),
+        ];
+        for &(key, expected) in &cases {
+            assert_eq!(canonical(key), expected);
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let mut prop = Properties::new();
+        prop.set("db.maxSize", "10");
+        assert_eq!(prop.get("db.max-size"), None);
+
+        prop.relaxed(true);
+        for key in &["db.maxSize", "db.max-size", "db.max_size", "DB_MAXSIZE"] {
+            assert_eq!(prop.get(key).unwrap(), "10", "{}", key);
+        }
+
+        prop.set("DB_MAXSIZE", "20");
+        assert_eq!(prop.len(), 1);
+        assert_eq!(prop.get("db.maxSize").unwrap(), "20");
+
+        assert_eq!(prop.remove("db.max_size").unwrap(), "20");
+        assert_eq!(prop.len(), 0);
+    }
+
+    #[test]
+    fn load() {
+        let mut prop = Properties::new();
+        prop.relaxed(true);
+        prop.set("db.maxSize", "1");
+        prop.load("DB_MAXSIZE=2\ndb.max-size=3\nserver.port=80\n".as_bytes())
+            .unwrap();
+        assert_eq!(prop.len(), 2);
+        assert_eq!(prop.get("db.maxSize").unwrap(), "3");
+        assert!(prop.relaxed_conflicts().is_empty());
+    }
+
+    #[test]
+    fn segments() {
+        let mut prop = Properties::new();
+        prop.relaxed(true);
+        prop.set("a.bc", "1");
+        assert_eq!(prop.get("ab.c"), None);
+        assert_eq!(prop.get("abc"), None);
+        assert_eq!(prop.get("A_BC").unwrap(), "1");
+
+        prop.set("ab.c", "2");
+        assert_eq!(prop.len(), 2);
+        assert_eq!(prop.get("a.bc").unwrap(), "1");
+        assert_eq!(prop.get("AB_C").unwrap(), "2");
+        assert!(prop.relaxed_conflicts().is_empty());
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut prop = Properties::new();
+        prop.set("db.max-size", "10");
+        prop.set("db.maxSize", "20");
+        prop.set("db.url", "jdbc:h2:mem");
+        prop.set("DB_URL", "jdbc:h2:file");
+        prop.set("server.port", "8080");
+        assert_eq!(
+            prop.relaxed_conflicts(),
+            vec![vec!["db.max-size", "db.maxSize"], vec!["DB_URL", "db.url"]]
+        );
+
+        // exact match wins, otherwise the lexically smallest stored key
+        prop.relaxed(true);
+        assert_eq!(prop.get("db.maxSize").unwrap(), "20");
+        assert_eq!(prop.get("db.max_size").unwrap(), "10");
+    }
+}
@@ -0,0 +1,286 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use super::listener::notify;
+use super::{Change, Properties, PropertiesError, ReadOption, Result};
+
+type ErrorHandler = Box<dyn Fn(&PropertiesError) + Send + Sync>;
+
+// Modification time and size of the file, a change of either triggers a
+// reload.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> std::io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        Ok(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+}
+
+// What the last attempt saw, the kind of error if the file was missing or
+// unreadable.
+type Seen = std::result::Result<Stamp, ErrorKind>;
+
+/// Properties backed by a file, which are reloaded when the modification
+/// time or the size of the file changes.
+///
+/// The file is loaded into a fresh table, which replaces the current one
+/// only if the whole file is parsed successfully, readers never see a half
+/// loaded table. Failed reloads keep the old values and are reported to the
+/// `on_error` handler.
+pub struct ReloadingProperties {
+    path: PathBuf,
+    opt: ReadOption,
+    props: Mutex<Properties>,
+    stamp: Mutex<Option<Seen>>,
+    // held by `reload` from reading the stamp to swapping the values, so
+    // that concurrent reloads can't leave an older content with a newer stamp
+    reloading: Mutex<()>,
+    on_error: Mutex<Option<ErrorHandler>>,
+}
+
+/// Background polling started by `ReloadingProperties::watch` or
+/// `PropertiesFile::watch`, stopped when dropped.
+pub struct Watcher {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    // Run f on a background thread, the receiver is disconnected when the
+    // watcher is dropped and f must return then.
+    pub(crate) fn spawn<F>(f: F) -> Self
+    where
+        F: FnOnce(Receiver<()>) + Send + 'static,
+    {
+        let (stop, rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || f(rx));
+        Watcher {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // disconnect the channel, which wakes up and stops the thread
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl ReloadingProperties {
+    /// Loads the file at `path`, which must succeed.
+    pub fn open<P: Into<PathBuf>>(path: P, opt: ReadOption) -> Result<Self> {
+        let result = Self {
+            path: path.into(),
+            opt,
+            props: Mutex::new(Properties::new()),
+            stamp: Mutex::new(None),
+            reloading: Mutex::new(()),
+            on_error: Mutex::new(None),
+        };
+        result.reload()?;
+        Ok(result)
+    }
+
+    /// Sets the handler called for every failed reload in `check` and
+    /// `watch`.
+    pub fn on_error<F>(&self, f: F)
+    where
+        F: Fn(&PropertiesError) + Send + Sync + 'static,
+    {
+        *self.on_error.lock().unwrap() = Some(Box::new(f));
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.props.lock().unwrap().get(key)
+    }
+
+    /// Runs `f` with the current properties, no reload happens meanwhile.
+    pub fn with<R, F: FnOnce(&mut Properties) -> R>(&self, f: F) -> R {
+        f(&mut self.props.lock().unwrap())
+    }
+
+    /// Loads the file again regardless of whether it changed.
+    pub fn reload(&self) -> Result<()> {
+        let _reloading = self.reloading.lock().unwrap();
+        let stamp = Stamp::of(&self.path);
+        // remember the failed attempt as well, so it's reported only once
+        *self.stamp.lock().unwrap() = Some(stamp.as_ref().copied().map_err(|e| e.kind()));
+        stamp.map_err(|e| PropertiesError::from(e).with_path(&self.path))?;
+
+        let mut fresh = Properties::new();
+        fresh.load_file(&self.path, &self.opt)?;
+
+        let mut props = self.props.lock().unwrap();
+        let changes = {
+            let data = props.data.lock().unwrap();
+            let fresh = fresh.data.lock().unwrap();
+            let mut keys: Vec<&String> = data.keys().chain(fresh.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter()
+                .map(|k| Change::new(k.clone(), data.get(k).cloned(), fresh.get(k).cloned()))
+                .filter(|c| c.old_value() != c.new_value())
+                .collect::<Vec<Change>>()
+        };
+        std::mem::swap(
+            &mut *props.data.lock().unwrap(),
+            &mut *fresh.data.lock().unwrap(),
+        );
+        props.origins = fresh.origins;
+        props.record_journal(&changes);
+        let listeners = props.listeners.clone();
+        drop(props);
+
+        // notify without holding the lock, so that listeners could read the
+        // new values
+        notify(&listeners, &changes);
+        Ok(())
+    }
+
+    /// Reloads the file if it changed since the last attempt, returns
+    /// whether new values were swapped in. Errors are passed to the
+    /// `on_error` handler as well, a file which stays missing or unreadable
+    /// is reported once.
+    pub fn check(&self) -> Result<bool> {
+        let seen = Stamp::of(&self.path).map_err(|e| e.kind());
+        if *self.stamp.lock().unwrap() == Some(seen) {
+            return Ok(false);
+        }
+        let result = self.reload().map(|_| true);
+        if let Err(e) = &result {
+            if let Some(f) = self.on_error.lock().unwrap().as_ref() {
+                f(e);
+            }
+        }
+        result
+    }
+
+    /// Starts a background thread calling `check` every `interval`, until the
+    /// returned `Watcher` is dropped.
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> Watcher {
+        let this = Arc::clone(self);
+        Watcher::spawn(move |rx| loop {
+            match rx.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    let _ = this.check();
+                }
+                _ => return,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::ReloadingProperties;
+    use crate::testutil::TempPath;
+    use crate::ReadOption;
+
+    #[test]
+    fn check() {
+        let path = TempPath::with_content("reload-check", "a=1\nb=1\n");
+        let prop = ReloadingProperties::open(&path, ReadOption::default()).unwrap();
+        let errors = Arc::new(AtomicUsize::new(0));
+        let cloned = errors.clone();
+        prop.on_error(move |_| {
+            cloned.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let cloned = changes.clone();
+        let _sub =
+            prop.with(|p| p.subscribe_prefix("", move |c| cloned.lock().unwrap().push(c.to_vec())));
+
+        assert!(!prop.check().unwrap());
+        assert_eq!(prop.get("a").unwrap(), "1");
+
+        fs::write(&path, "a=22\n").unwrap();
+        assert!(prop.check().unwrap());
+        assert_eq!(prop.get("a").unwrap(), "22");
+        assert_eq!(prop.get("b"), None);
+        {
+            let changes = changes.lock().unwrap();
+            assert_eq!(changes.len(), 1);
+            let batch: Vec<(&str, Option<&str>, Option<&str>)> = changes[0]
+                .iter()
+                .map(|c| (c.key(), c.old_value(), c.new_value()))
+                .collect();
+            assert_eq!(
+                batch,
+                vec![("a", Some("1"), Some("22")), ("b", Some("1"), None)]
+            );
+        }
+
+        // a broken file keeps the old values, and is reported once
+        fs::write(&path, "a=333\nb=\\uzzzz\n").unwrap();
+        if prop.check().is_ok() {
+            panic!("reload should failed");
+        }
+        assert!(!prop.check().unwrap());
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+        assert_eq!(prop.get("a").unwrap(), "22");
+
+        fs::remove_file(&path).unwrap();
+        if prop.check().is_ok() {
+            panic!("missing file should failed");
+        }
+        for _ in 0..3 {
+            assert!(!prop.check().unwrap());
+        }
+        assert_eq!(errors.load(Ordering::SeqCst), 2);
+        assert_eq!(prop.get("a").unwrap(), "22");
+
+        fs::write(&path, "a=4444\n").unwrap();
+        assert!(prop.check().unwrap());
+        assert_eq!(prop.get("a").unwrap(), "4444");
+    }
+
+    #[test]
+    fn watch() {
+        let path = TempPath::with_content("reload-watch", "a=1\n");
+        let prop = Arc::new(ReloadingProperties::open(&path, ReadOption::default()).unwrap());
+        let watcher = prop.watch(Duration::from_millis(10));
+
+        fs::write(&path, "a=22\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while prop.get("a").unwrap() != "22" {
+            if Instant::now() > deadline {
+                panic!("file is not reloaded");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(watcher);
+
+        fs::write(&path, "a=333\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(prop.get("a").unwrap(), "22");
+    }
+}
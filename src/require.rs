@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::str::FromStr;
+
+use super::{Properties, PropertiesError, Result};
+
+const MAX_SUGGESTIONS: usize = 3;
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+impl Properties {
+    /// Returns up to three existing keys closest to `key` by edit distance,
+    /// ignoring those too different to be a plausible typo.
+    pub fn suggest(&mut self, key: &str) -> Vec<String> {
+        let limit = (key.chars().count() / 3).max(2);
+        let data = self.data.lock().unwrap();
+        let mut candidates: Vec<(usize, &String)> = data
+            .keys()
+            .map(|k| (edit_distance(key, k), k))
+            .filter(|&(d, _)| d <= limit)
+            .collect();
+        candidates.sort();
+        candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, k)| k.clone())
+            .collect()
+    }
+
+    fn describe_missing(&mut self, key: &str) -> String {
+        let suggestions = self.suggest(key);
+        if suggestions.is_empty() {
+            return format!("'{}'", key);
+        }
+        let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+        format!("'{}' (did you mean {}?)", key, quoted.join(", "))
+    }
+
+    /// Like `get`, but a missing key is an error listing the closest existing
+    /// keys, e.g. `missing key 'databse.url' (did you mean 'database.url'?)`.
+    pub fn require(&mut self, key: &str) -> Result<String> {
+        match self.try_get(key)? {
+            Some(val) => Ok(val),
+            None => Err(PropertiesError::new(format!(
+                "missing key {}",
+                self.describe_missing(key)
+            ))),
+        }
+    }
+
+    /// Like `require`, and parses the value into `T`.
+    pub fn require_as<T>(&mut self, key: &str) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: Error + 'static,
+    {
+        let val = self.require(key)?;
+        val.parse::<T>().map_err(|e| {
+            PropertiesError::with_cause(
+                format!(
+                    "invalid value '{}' for key '{}'",
+                    self.masked(key, &val),
+                    key
+                ),
+                Some(Box::new(e)),
+            )
+        })
+    }
+
+    /// Returns the values of all `keys` in order, or an error reporting every
+    /// missing key at once. A value failing to decode is reported as is.
+    pub fn require_all(&mut self, keys: &[&str]) -> Result<Vec<String>> {
+        let mut result = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for key in keys {
+            match self.try_get(key)? {
+                Some(val) => result.push(val),
+                None => missing.push(self.describe_missing(key)),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(PropertiesError::new(format!(
+                "missing keys {}",
+                missing.join(", ")
+            )));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::edit_distance;
+    use crate::testutil::props;
+
+    const DATA: &str = "database.url=jdbc:h2:mem\ndatabase.user=root\nserver.port=8080\n";
+
+    #[test]
+    fn distance() {
+        let cases = vec![
+            ("", "", 0),
+            ("abc", "", 3),
+            ("kitten", "sitting", 3),
+            ("databse.url", "database.url", 1),
+            ("你好", "你们好", 1),
+        ];
+        for &(a, b, expected) in &cases {
+            assert_eq!(edit_distance(a, b), expected, "{} {}", a, b);
+            assert_eq!(edit_distance(b, a), expected, "{} {}", b, a);
+        }
+    }
+
+    #[test]
+    fn require() {
+        let mut prop = props(DATA);
+        assert_eq!(prop.require("database.url").unwrap(), "jdbc:h2:mem");
+        assert_eq!(prop.require_as::<u16>("server.port").unwrap(), 8080);
+
+        let cases = vec![
+            (
+                "databse.url",
+                "missing key 'databse.url' (did you mean 'database.url'?)",
+            ),
+            (
+                "database.usr",
+                "missing key 'database.usr' (did you mean 'database.user', 'database.url'?)",
+            ),
+            ("client.timeout", "missing key 'client.timeout'"),
+        ];
+        for &(key, expected) in &cases {
+            match prop.require(key) {
+                Ok(_) => panic!("key {} should be missing", key),
+                Err(e) => assert_eq!(format!("{}", e), expected),
+            }
+        }
+
+        match prop.require_as::<u16>("database.user") {
+            Ok(_) => panic!("parse should failed"),
+            Err(e) => {
+                let msg = format!("{}", e);
+                assert!(
+                    msg.starts_with("invalid value 'root' for key 'database.user'"),
+                    "{}",
+                    msg
+                );
+            }
+        }
+        prop.redact_key("database.user");
+        match prop.require_as::<u16>("database.user") {
+            Ok(_) => panic!("parse should failed"),
+            Err(e) => {
+                let msg = format!("{}", e);
+                assert!(!msg.contains("root"), "{}", msg);
+            }
+        }
+    }
+
+    #[test]
+    fn require_all() {
+        let mut prop = props(DATA);
+        let values = prop.require_all(&["server.port", "database.user"]).unwrap();
+        assert_eq!(values, vec!["8080", "root"]);
+
+        match prop.require_all(&["databse.url", "server.port", "client.timeout"]) {
+            Ok(_) => panic!("keys should be missing"),
+            Err(e) => assert_eq!(
+                format!("{}", e),
+                "missing keys 'databse.url' (did you mean 'database.url'?), 'client.timeout'"
+            ),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Helpers shared by the unit tests.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use super::Properties;
+
+// Properties loaded from text, which must be valid.
+pub(crate) fn props(text: &str) -> Properties {
+    let mut prop = Properties::new();
+    prop.load(text.as_bytes()).unwrap();
+    prop
+}
+
+// A path in the temp directory unique to the test, nothing exists there at
+// first. Whatever was created at it, including a `.lock` sidecar, is removed
+// when dropped, so also when the test fails.
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("props-{}-{}", name, std::process::id()));
+        let result = TempPath(path);
+        result.clean();
+        result
+    }
+
+    // Same as `new`, with a file holding content.
+    pub(crate) fn with_content(name: &str, content: &str) -> Self {
+        let result = TempPath::new(name);
+        fs::write(&result.0, content).unwrap();
+        result
+    }
+
+    fn clean(&self) {
+        let mut lock = self.0.as_os_str().to_owned();
+        lock.push(".lock");
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_dir_all(&self.0);
+        let _ = fs::remove_file(PathBuf::from(lock));
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        self.clean();
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for TempPath {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use super::{Change, Properties, PropertiesError, Result};
+
+/// Changes staged by `Properties::transaction`, nothing is visible outside
+/// of the transaction until it's committed.
+pub struct Transaction<'a> {
+    prop: &'a Properties,
+    staged: Vec<(String, Option<String>)>,
+    index: HashMap<String, usize>,
+}
+
+impl Transaction<'_> {
+    // Find the key a change is staged under, a staged key wins over a stored
+    // one, which is resolved as in `Properties::get`.
+    fn resolve(&self, key: &str) -> String {
+        if self.index.contains_key(key) {
+            return key.to_string();
+        }
+        let data = self.prop.data.lock().unwrap();
+        self.prop
+            .resolve_key(&data, key)
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    fn stage(&mut self, key: String, value: Option<String>) {
+        match self.index.get(&key) {
+            Some(&i) => self.staged[i].1 = value,
+            None => {
+                self.index.insert(key.clone(), self.staged.len());
+                self.staged.push((key, value));
+            }
+        }
+    }
+
+    // The stored value of key with the staged changes applied, as is.
+    pub(crate) fn get_raw(&self, key: &str) -> Option<String> {
+        let key = self.resolve(key);
+        match self.index.get(&key) {
+            Some(&i) => self.staged[i].1.clone(),
+            None => self.prop.data.lock().unwrap().get(&key).cloned(),
+        }
+    }
+
+    fn try_get(&self, key: &str) -> Result<Option<String>> {
+        match self.get_raw(key) {
+            Some(v) => self.prop.decode_value(&self.resolve(key), v).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value of `key` with the staged changes applied, decoded
+    /// by the matching codec like `Properties::get`.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.try_get(key).unwrap_or(None)
+    }
+
+    /// Same as `get`, a missing key or a value failing to decode is an
+    /// error, e.g. to validate the staged changes before the commit.
+    pub fn require(&self, key: &str) -> Result<String> {
+        self.try_get(key)?
+            .ok_or_else(|| PropertiesError::new(format!("missing key '{}'", key)))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        let key = self.resolve(key);
+        self.stage(key, Some(value.to_string()));
+    }
+
+    /// Stages the removal of `key`, returns its value before as stored, like
+    /// `Properties::remove`.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let key = self.resolve(key);
+        let previous = self.get_raw(&key);
+        self.stage(key, None);
+        previous
+    }
+}
+
+impl Properties {
+    /// Runs `f` with a transaction staging sets and removes. When `f` returns
+    /// Ok all the staged changes are applied at once, under a single lock of
+    /// the data, and listeners are notified with a single event. An error
+    /// rolls everything back and is returned as is.
+    pub fn transaction<R, E, F>(&mut self, f: F) -> std::result::Result<R, E>
+    where
+        F: FnOnce(&mut Transaction) -> std::result::Result<R, E>,
+    {
+        let mut tx = Transaction {
+            prop: self,
+            staged: Vec::new(),
+            index: HashMap::new(),
+        };
+        let result = f(&mut tx)?;
+        let staged = tx.staged;
+
+        let mut data = self.data.lock().unwrap();
+        let changes: Vec<Change> = staged
+            .into_iter()
+            .map(|(k, v)| {
+                let old = match &v {
+                    Some(v) => data.insert(k.clone(), v.clone()),
+                    None => data.remove(&k),
+                };
+                Change::new(k, old, v)
+            })
+            .collect();
+        drop(data);
+
+        self.batch(|p| {
+            for change in changes {
+                p.origins.remove(change.key());
+                p.record_change(change);
+            }
+        });
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Properties;
+    use crate::PropertiesError;
+
+    #[test]
+    fn commit() {
+        let mut prop = Properties::new();
+        prop.load("db.host=db1\ndb.port=5432\ndb.user=admin\ndb.pool=4\n".as_bytes())
+            .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cloned = events.clone();
+        let _sub = prop.subscribe_prefix("db", move |c| cloned.lock().unwrap().push(c.len()));
+
+        let port = prop
+            .transaction(|tx| {
+                tx.set("db.host", "db2");
+                tx.set("db.port", "5433");
+                tx.set("db.port", "5434");
+                assert_eq!(tx.remove("db.pool").unwrap(), "4");
+                assert_eq!(tx.get("db.pool"), None);
+                tx.set("db.user", "admin");
+                tx.require("db.port")
+            })
+            .unwrap();
+        assert_eq!(port, "5434");
+        assert_eq!(prop.get("db.host").unwrap(), "db2");
+        assert_eq!(prop.get("db.port").unwrap(), "5434");
+        assert_eq!(prop.get("db.pool"), None);
+        // db.user is unchanged, so only three changes are notified
+        assert_eq!(*events.lock().unwrap(), vec![3]);
+    }
+
+    #[test]
+    fn rollback() {
+        let mut prop = Properties::new();
+        prop.set("db.host", "db1");
+        let events = Arc::new(Mutex::new(0));
+        let cloned = events.clone();
+        let _sub = prop.subscribe_prefix("", move |_| *cloned.lock().unwrap() += 1);
+
+        let result = prop.transaction(|tx| {
+            tx.set("db.host", "db2");
+            tx.remove("db.host");
+            tx.require("db.host")?;
+            Ok::<_, PropertiesError>(())
+        });
+        match result {
+            Ok(_) => panic!("transaction should failed"),
+            Err(e) => assert_eq!(format!("{}", e), "missing key 'db.host'"),
+        }
+        assert_eq!(prop.get("db.host").unwrap(), "db1");
+        assert_eq!(*events.lock().unwrap(), 0);
+    }
+}
@@ -0,0 +1,247 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+
+use super::query::join_prefix;
+use super::Properties;
+
+// Strip the trailing separator, so that both "db" and "db." address the
+// same subtree.
+pub(crate) fn normalize_prefix(prefix: &str) -> &str {
+    prefix.strip_suffix('.').unwrap_or(prefix)
+}
+
+// Return the part of key below prefix, the prefix is matched on whole
+// segments, which means "db" matches "db.url" but not "dbx.url". An empty
+// prefix matches every key.
+pub(crate) fn strip_segment_prefix<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    let prefix = normalize_prefix(prefix);
+    if prefix.is_empty() {
+        return Some(key);
+    }
+    key.strip_prefix(prefix)?.strip_prefix('.')
+}
+
+// Maps the keys of a copy made by `subtree` or `with_prefix` back to the
+// keys a rule was registered for, so that the glob patterns inherited from
+// the original, e.g. redaction patterns, keep matching the same entries.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Scope {
+    // (prefix in the copy, prefix in the original), the latest copy first
+    steps: Vec<(String, String)>,
+}
+
+impl Scope {
+    // The keys under copy in the copy are the keys under original.
+    fn new(copy: &str, original: &str) -> Scope {
+        Scope {
+            steps: vec![(
+                normalize_prefix(copy).to_string(),
+                normalize_prefix(original).to_string(),
+            )],
+        }
+    }
+
+    // The scope in the copy of a rule which had the scope rule in the
+    // original.
+    pub(crate) fn compose(&self, rule: &Scope) -> Scope {
+        let mut steps = self.steps.clone();
+        steps.extend(rule.steps.iter().cloned());
+        Scope { steps }
+    }
+
+    // The original key of key, which is returned as is if it was added to
+    // the copy outside of its prefix.
+    pub(crate) fn original<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        let mut result = Cow::Borrowed(key);
+        for (copy, original) in &self.steps {
+            match strip_segment_prefix(&result, copy) {
+                Some(rest) => result = Cow::Owned(join_prefix(original, rest)),
+                None => return Cow::Borrowed(key),
+            }
+        }
+        result
+    }
+
+    // The key in the copy of an original key, None if it's not copied. Used
+    // for the rules on exact keys, which are mapped when copied.
+    pub(crate) fn copy<'a>(&self, key: &'a str) -> Option<Cow<'a, str>> {
+        let mut result = Cow::Borrowed(key);
+        for (copy, original) in self.steps.iter().rev() {
+            let rest = strip_segment_prefix(&result, original)?;
+            if rest.is_empty() {
+                return None;
+            }
+            result = Cow::Owned(join_prefix(copy, rest));
+        }
+        Some(result)
+    }
+}
+
+impl Properties {
+    // A copy holding data with the relaxed mode, the aliases, the redaction
+    // rules and the codecs of self, its keys under copy map to the keys
+    // under original in self.
+    fn derive(&self, data: HashMap<String, String>, copy: &str, original: &str) -> Properties {
+        let scope = Scope::new(copy, original);
+        let mut result = Properties::from_map(data);
+        result.relaxed = self.relaxed;
+        result.aliases = self.aliases.rebase(&scope);
+        result.redaction = self.redaction.rebase(&scope);
+        result.codecs = self.codecs.rebase(&scope);
+        result
+    }
+
+    /// Returns a copy of all the keys under `prefix` with the prefix removed,
+    /// e.g. `subtree("db")` maps `db.url` to `url`.
+    ///
+    /// The copy keeps the relaxed mode, the aliases, the redaction rules and
+    /// the codecs, which still apply to the same entries, e.g. a rule for
+    /// `db.password` masks `password` in `subtree("db")`. Listeners, the
+    /// journal, the origins and access tracking are not copied.
+    pub fn subtree(&mut self, prefix: &str) -> Properties {
+        let data = self.data.lock().unwrap();
+        let mut result = HashMap::new();
+        for (k, v) in data.iter() {
+            if let Some(rest) = strip_segment_prefix(k, prefix) {
+                if !rest.is_empty() {
+                    result.insert(rest.to_string(), v.clone());
+                }
+            }
+        }
+        self.derive(result, "", prefix)
+    }
+
+    /// Returns a copy with every key prefixed by `prefix`, the reverse of
+    /// `subtree`, which keeps the rules as `subtree` does.
+    pub fn with_prefix(&mut self, prefix: &str) -> Properties {
+        let prefix = normalize_prefix(prefix);
+        let data = self.data.lock().unwrap();
+        let mut result = HashMap::new();
+        for (k, v) in data.iter() {
+            if prefix.is_empty() {
+                result.insert(k.clone(), v.clone());
+            } else {
+                result.insert(format!("{}.{}", prefix, k), v.clone());
+            }
+        }
+        self.derive(result, prefix, "")
+    }
+
+    /// Lists the immediate child segments of `prefix` in lexical order, e.g.
+    /// `children("db")` returns `["pool", "url"]` for `db.url` and
+    /// `db.pool.size`.
+    pub fn children(&mut self, prefix: &str) -> Vec<String> {
+        let data = self.data.lock().unwrap();
+        let mut result = BTreeSet::new();
+        for k in data.keys() {
+            if let Some(rest) = strip_segment_prefix(k, prefix) {
+                if let Some(child) = rest.split('.').next() {
+                    if !child.is_empty() {
+                        result.insert(child.to_string());
+                    }
+                }
+            }
+        }
+        result.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testutil::props;
+    use crate::{Base64Codec, WriteOption};
+
+    const DATA: &str = "db=root\ndb.url=jdbc:h2:mem\ndb.pool.size=8\ndb.pool.idle=2\ndbx.url=none\nserver.port=8080\n";
+
+    #[test]
+    fn subtree() {
+        let cases = vec![
+            (
+                "db",
+                vec![
+                    ("url", "jdbc:h2:mem"),
+                    ("pool.size", "8"),
+                    ("pool.idle", "2"),
+                ],
+            ),
+            (
+                "db.",
+                vec![
+                    ("url", "jdbc:h2:mem"),
+                    ("pool.size", "8"),
+                    ("pool.idle", "2"),
+                ],
+            ),
+            ("db.pool", vec![("size", "8"), ("idle", "2")]),
+            ("server.port", vec![]),
+            ("none", vec![]),
+        ];
+        for (prefix, r) in &cases {
+            let mut sub = props(DATA).subtree(prefix);
+            if r.len() != sub.len() {
+                panic!("invalid items, expect={} got={}", r.len(), sub.len());
+            }
+            for l in r {
+                match sub.get(l.0) {
+                    Some(val) => assert_eq!(val, l.1, "prefix {}", prefix),
+                    None => panic!("key {} doesn't exist under {}", l.0, prefix),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn with_prefix() {
+        let mut prop = props(DATA).subtree("db").with_prefix("spring.datasource.");
+        assert_eq!(prop.len(), 3);
+        assert_eq!(prop.get("spring.datasource.url").unwrap(), "jdbc:h2:mem");
+        assert_eq!(prop.get("spring.datasource.pool.size").unwrap(), "8");
+
+        let mut prop = props(DATA).with_prefix("");
+        assert_eq!(prop.len(), 6);
+        assert_eq!(prop.get("db").unwrap(), "root");
+    }
+
+    #[test]
+    fn rules() {
+        let mut prop = props(DATA);
+        prop.relaxed(true);
+        prop.alias("db.user", "db.username");
+        prop.alias("legacy.pool", "db.pool.max");
+        prop.set("db.user", "sa");
+        prop.set("db.password", "ENC(czNjcmV0)");
+        prop.codec("ENC(", ")", Base64Codec);
+        prop.encode_pattern("db.secret", "ENC(");
+
+        let mut db = prop.subtree("db");
+        assert_eq!(db.get("POOL_SIZE").unwrap(), "8");
+        assert_eq!(db.get("username").unwrap(), "sa");
+        assert_eq!(db.get("password").unwrap(), "s3cret");
+        // the alias from outside of the subtree is dropped
+        assert_eq!(db.get("pool.max"), None);
+
+        db.set("secret", "abc");
+        db.set("other", "abc");
+        let mut out = Vec::new();
+        db.with_prefix("x")
+            .store(&mut out, &WriteOption::default())
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("x.secret=ENC(YWJj)\n"), "{}", out);
+        assert!(out.contains("x.other=abc\n"), "{}", out);
+    }
+
+    #[test]
+    fn children() {
+        let cases = vec![
+            ("", vec!["db", "dbx", "server"]),
+            ("db", vec!["pool", "url"]),
+            ("db.pool", vec!["idle", "size"]),
+            ("db.url", vec![]),
+            ("none", vec![]),
+        ];
+        for (prefix, expected) in &cases {
+            assert_eq!(&props(DATA).children(prefix), expected, "prefix {}", prefix);
+        }
+    }
+}
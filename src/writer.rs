@@ -109,7 +109,11 @@ fn save_comment(
     return Ok(result);
 }
 
-fn save_convert(data: &String, escape_space: bool, escape_unicode: bool) -> Result<Vec<u8>> {
+pub(crate) fn save_convert(
+    data: &String,
+    escape_space: bool,
+    escape_unicode: bool,
+) -> Result<Vec<u8>> {
     let bytes = data.as_bytes();
     let mut result: Vec<u8> = Vec::new();
 
@@ -173,8 +177,9 @@ impl Properties {
 
         let data = self.data.lock().unwrap();
         for (k, v) in data.iter() {
+            let encoded = self.encode_value(k, v)?;
             let key = save_convert(k, true, opt.escape_unicode)?;
-            let val = save_convert(v, false, opt.escape_unicode)?;
+            let val = save_convert(encoded.as_ref().unwrap_or(v), false, opt.escape_unicode)?;
 
             writer.write(&key)?;
             writer.write(b"=")?;